@@ -1,6 +1,8 @@
 use std::ops::{Add, AddAssign, Sub, SubAssign};
 use super::opcodes::{BYTE, NIBBLE};
 
+// An address as the three nibbles of an NNN operand. The first field holds everything
+// above the low byte, so addresses past 0xFFF fit too for XO-CHIP's 64K of memory.
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Address (pub u8, pub u8, pub u8);
 
@@ -15,7 +17,7 @@ impl Add<u16> for Address {
 
     fn add(self, other: u16) -> Self {
         let s: u16 = self.into();
-        (s + other).into()
+        s.wrapping_add(other).into()
     }
 }
 
@@ -42,30 +44,30 @@ impl SubAssign<u16> for Address {
 
 impl From<u16> for Address {
     fn from(n: u16) -> Self {
-        Self (((n & 0x0F00) >> BYTE) as u8, ((n & 0x00F0) >> NIBBLE) as u8, (n & 0x000F >> 0) as u8)
+        Self (((n & 0xFF00) >> BYTE) as u8, ((n & 0x00F0) >> NIBBLE) as u8, (n & 0x000F) as u8)
     }
 }
 
 impl From<Address> for u16 {
     fn from(constant: Address) -> Self {
-        (constant.0 as u16) << BYTE | (constant.1 as u16) << NIBBLE | (constant.1 as u16)  
+        (constant.0 as u16) << BYTE | (constant.1 as u16) << NIBBLE | (constant.2 as u16)
     }
 }
 
 impl From<Address> for usize {
     fn from(constant: Address) -> Self {
-        (constant.0 as usize) << BYTE | (constant.1 as usize) << NIBBLE | (constant.1 as usize)  
+        (constant.0 as usize) << BYTE | (constant.1 as usize) << NIBBLE | (constant.2 as usize)
     }
 }
 
 impl From<&Address> for usize {
     fn from(constant: &Address) -> Self {
-        (constant.0 as usize) << BYTE | (constant.1 as usize) << NIBBLE | (constant.1 as usize)  
+        (constant.0 as usize) << BYTE | (constant.1 as usize) << NIBBLE | (constant.2 as usize)
     }
 }
 impl From<usize> for Address {
     fn from(n: usize) -> Self {
-        Self (((n & 0x0F00) >> BYTE) as u8, ((n & 0x00F0) >> NIBBLE) as u8, (n & 0x000F >> 0) as u8)
+        Self (((n & 0xFF00) >> BYTE) as u8, ((n & 0x00F0) >> NIBBLE) as u8, (n & 0x000F) as u8)
     }
 }
 
@@ -0,0 +1,585 @@
+// Two pass assembler for Cowgod style CHIP-8 mnemonics, the same syntax the disassembler
+// writes with `Syntax::Cowgod`.
+//
+//     ; comments run to the end of the line
+//     SPEED   equ 3              ; constants, `SPEED = 3` works too
+//     start:  LD V0, SPEED * 2   ; labels end in a colon, operands are expressions
+//             CALL draw          ; labels may be used before they are defined
+//             JP start
+//     draw:   DRW V0, V1, 5
+//             RET
+//     sprite: db #F0, %10010000, "AB"
+//             dw #1234
+//             org #300           ; continue assembling at another address
+//             include "more.asm" ; relative to the including file
+//
+// The first pass works out the address of every line and collects symbols, the second
+// encodes instructions and data now that every label is known.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::address::Address;
+use super::disasm::{ADDRESS_SPACE, DEFAULT_ORIGIN};
+use super::error::{AsmError, CpuError};
+use super::expr;
+use super::instruction::Instruction;
+use super::processor::CPU;
+
+// Deepest chain of constants referring to constants, which also catches cycles
+const MAX_SYMBOL_DEPTH: usize = 64;
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// An assembled program, `bytes` are meant to be loaded at `origin`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rom {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub symbols: BTreeMap<String, i64>,
+}
+
+impl Rom {
+    pub fn copy_to(&self, cpu: &mut CPU) -> Result<(), CpuError> {
+        cpu.raw_copy_to_mem(self.origin as usize, &self.bytes)
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<i64> {
+        self.symbols.get(name).copied()
+    }
+}
+
+// Writes the symbol map, one `name = value` per line
+impl fmt::Display for Rom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.symbols {
+            writeln!(f, "{} = 0x{:X}", name, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembler {
+    pub origin: u16,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self { origin: DEFAULT_ORIGIN }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Empty,
+    Instruction { mnemonic: String, operands: Vec<String> },
+    Db(Vec<String>),
+    Dw(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+struct Statement {
+    file: String,
+    line: usize,
+    address: u16,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Symbol {
+    Label(u16),
+    Constant(String),
+}
+
+// Where a statement came from, for error messages
+#[derive(Debug, Clone)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error<S: Into<String>>(&self, message: S) -> AsmError {
+        AsmError { file: self.file.clone(), line: self.line, message: message.into() }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Program {
+    statements: Vec<Statement>,
+    symbols: BTreeMap<String, (Symbol, Location)>,
+}
+
+impl Assembler {
+    pub fn new(origin: u16) -> Self {
+        Self { origin }
+    }
+
+    // Includes are looked up relative to the working directory
+    pub fn assemble(&self, source: &str) -> Result<Rom, AsmError> {
+        self.assemble_source(source, "<source>", Path::new("."))
+    }
+
+    pub fn assemble_file<P: AsRef<Path>>(&self, path: P) -> Result<Rom, AsmError> {
+        let path = path.as_ref();
+        let source = read_source(path, &Location { file: path.display().to_string(), line: 0 })?;
+
+        self.assemble_source(&source, &path.display().to_string(), &parent(path))
+    }
+
+    fn assemble_source(&self, source: &str, name: &str, dir: &Path) -> Result<Rom, AsmError> {
+        let mut program = Program::default();
+        let mut address = self.origin as usize;
+
+        self.first_pass(&mut program, source, name, dir, &mut address, 0)?;
+        self.second_pass(&program)
+    }
+
+    fn first_pass(
+        &self,
+        program: &mut Program,
+        source: &str,
+        name: &str,
+        dir: &Path,
+        // kept wider than an address so a program may end on the last byte of memory
+        address: &mut usize,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        for (index, text) in source.lines().enumerate() {
+            let location = Location { file: name.to_string(), line: index + 1 };
+            let (label, body) = split_label(strip_comment(text));
+
+            if let Some(label) = label {
+                let value = u16::try_from(*address)
+                    .map_err(|_| location.error(format!("label {} is past the end of the address space", label)))?;
+                define(program, label, Symbol::Label(value), &location)?;
+            }
+
+            let (word, rest) = split_word(body);
+
+            // `NAME equ expr` and `NAME = expr`
+            let (second, value) = split_word(rest);
+            if !word.is_empty() && (second.eq_ignore_ascii_case("equ") || second == "=") {
+                define(program, word, Symbol::Constant(value.to_string()), &location)?;
+                continue;
+            }
+            if let Some(value) = rest.strip_prefix('=') {
+                define(program, word, Symbol::Constant(value.trim().to_string()), &location)?;
+                continue;
+            }
+
+            let kind = match word.to_ascii_lowercase().as_str() {
+                "" => Kind::Empty,
+                "include" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(location.error("includes nested too deeply"));
+                    }
+
+                    let path = dir.join(unquote(rest).ok_or_else(|| location.error("include needs a quoted file name"))?);
+                    let included = read_source(&path, &location)?;
+
+                    self.first_pass(program, &included, &path.display().to_string(), &parent(&path), address, depth + 1)?;
+                    continue;
+                }
+                "org" => {
+                    let target = evaluate(program, rest, &location)?;
+                    *address = to_address(target, &location)? as usize;
+                    continue;
+                }
+                "db" => Kind::Db(split_operands(rest)),
+                "dw" => Kind::Dw(split_operands(rest)),
+                mnemonic => Kind::Instruction { mnemonic: mnemonic.to_string(), operands: split_operands(rest) },
+            };
+
+            let size = match &kind {
+                Kind::Empty => 0,
+                Kind::Instruction { operands, .. } if operands.iter().any(|text| is_long(text)) => 4,
+                Kind::Instruction { .. } => 2,
+                Kind::Db(items) => items.iter().map(|item| unquote(item).map_or(1, |s| s.len())).sum(),
+                Kind::Dw(items) => items.len() * 2,
+            };
+
+            if size == 0 {
+                continue;
+            }
+            if *address + size > ADDRESS_SPACE {
+                return Err(location.error("program runs past the end of the address space"));
+            }
+
+            program.statements.push(Statement { file: location.file.clone(), line: location.line, address: *address as u16, kind });
+            *address += size;
+        }
+
+        Ok(())
+    }
+
+    fn second_pass(&self, program: &Program) -> Result<Rom, AsmError> {
+        let mut bytes: Vec<u8> = Vec::new();
+
+        for statement in &program.statements {
+            let location = Location { file: statement.file.clone(), line: statement.line };
+
+            let emitted = match &statement.kind {
+                Kind::Empty => continue,
+                Kind::Instruction { mnemonic, operands } => {
+                    let word = encode(program, mnemonic, operands, &location)?.encode();
+                    let mut code = vec![(word >> 8) as u8, word as u8];
+
+                    if let Some(Operand::Long(text)) = operands.last().map(|text| operand(text)) {
+                        let address = to_address(evaluate(program, &text, &location)?, &location)?;
+                        code.extend(&address.to_be_bytes());
+                    }
+
+                    code
+                }
+                Kind::Db(items) => {
+                    let mut data = Vec::new();
+
+                    for item in items {
+                        match unquote(item) {
+                            Some(text) => data.extend(text.bytes()),
+                            None => data.push(to_byte(evaluate(program, item, &location)?, &location)?),
+                        }
+                    }
+
+                    data
+                }
+                Kind::Dw(items) => {
+                    let mut data = Vec::new();
+
+                    for item in items {
+                        let value = evaluate(program, item, &location)?;
+                        if !(-0x8000..=0xFFFF).contains(&value) {
+                            return Err(location.error(format!("{} does not fit in a word", value)));
+                        }
+                        data.extend(&(value as u16).to_be_bytes());
+                    }
+
+                    data
+                }
+            };
+
+            if statement.address < self.origin {
+                return Err(location.error(format!("address {:#X} is below the origin {:#X}", statement.address, self.origin)));
+            }
+
+            let offset = (statement.address - self.origin) as usize;
+            if bytes.len() < offset + emitted.len() {
+                bytes.resize(offset + emitted.len(), 0);
+            }
+            bytes[offset..offset + emitted.len()].copy_from_slice(&emitted);
+        }
+
+        let mut symbols = BTreeMap::new();
+        for (name, (_, location)) in &program.symbols {
+            symbols.insert(name.clone(), resolve(program, name, location, 0)?);
+        }
+
+        Ok(Rom { origin: self.origin, bytes, symbols })
+    }
+}
+
+fn read_source(path: &Path, location: &Location) -> Result<String, AsmError> {
+    fs::read_to_string(path).map_err(|err| location.error(format!("cannot read {}: {}", path.display(), err)))
+}
+
+fn parent(path: &Path) -> PathBuf {
+    path.parent().map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+}
+
+fn define(program: &mut Program, name: &str, symbol: Symbol, location: &Location) -> Result<(), AsmError> {
+    if !expr::is_identifier(name) {
+        return Err(location.error(format!("'{}' is not a valid symbol name", name)));
+    }
+
+    if let Some((_, previous)) = program.symbols.get(name) {
+        return Err(location.error(format!("'{}' is already defined at line {}", name, previous.line)));
+    }
+
+    program.symbols.insert(name.to_string(), (symbol, location.clone()));
+    Ok(())
+}
+
+fn resolve(program: &Program, name: &str, location: &Location, depth: usize) -> Result<i64, AsmError> {
+    if depth > MAX_SYMBOL_DEPTH {
+        return Err(location.error(format!("'{}' is defined in terms of itself", name)));
+    }
+
+    match program.symbols.get(name) {
+        Some((Symbol::Label(address), _)) => Ok(*address as i64),
+        Some((Symbol::Constant(source), defined)) => {
+            let mut failure = None;
+            let value = expr::evaluate(source, |name| {
+                resolve(program, name, defined, depth + 1).map_err(|err| {
+                    failure = Some(err);
+                    String::new()
+                })
+            });
+
+            match (value, failure) {
+                (Ok(value), _) => Ok(value),
+                (Err(_), Some(err)) => Err(err),
+                (Err(message), None) => Err(defined.error(message)),
+            }
+        }
+        None => Err(location.error(format!("undefined symbol '{}'", name))),
+    }
+}
+
+fn evaluate(program: &Program, source: &str, location: &Location) -> Result<i64, AsmError> {
+    if source.trim().is_empty() {
+        return Err(location.error("missing operand"));
+    }
+
+    let mut failure = None;
+    let value = expr::evaluate(source, |name| {
+        resolve(program, name, location, 0).map_err(|err| {
+            failure = Some(err);
+            String::new()
+        })
+    });
+
+    match (value, failure) {
+        (Ok(value), _) => Ok(value),
+        (Err(_), Some(err)) => Err(err),
+        (Err(message), None) => Err(location.error(message)),
+    }
+}
+
+fn to_address(value: i64, location: &Location) -> Result<u16, AsmError> {
+    if (0..=0xFFFF).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(location.error(format!("{:#X} is not a valid address", value)))
+    }
+}
+
+// Negative values down to -128 are accepted as their two's complement byte
+fn to_byte(value: i64, location: &Location) -> Result<u8, AsmError> {
+    if (-0x80..=0xFF).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(location.error(format!("{} does not fit in a byte", value)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    LargeFont,
+    Bcd,
+    Pitch,
+    // the RPL user flags
+    Flags,
+    // `LONG expr`, the 16 bit address following XO-CHIP's F000
+    Long(String),
+    Value(String),
+}
+
+fn operand(text: &str) -> Operand {
+    let upper = text.to_ascii_uppercase();
+
+    match upper.as_str() {
+        "I" => return Operand::I,
+        "[I]" => return Operand::IndirectI,
+        "DT" => return Operand::DelayTimer,
+        "ST" => return Operand::SoundTimer,
+        "K" => return Operand::Key,
+        "F" => return Operand::Font,
+        "HF" => return Operand::LargeFont,
+        "B" => return Operand::Bcd,
+        "PITCH" => return Operand::Pitch,
+        "R" => return Operand::Flags,
+        _ => {}
+    }
+
+    if upper.starts_with("LONG ") {
+        return Operand::Long(text[5..].trim().to_string());
+    }
+
+    match upper.strip_prefix('V') {
+        Some(digit) if digit.len() == 1 => match u8::from_str_radix(digit, 16) {
+            Ok(register) => Operand::Register(register),
+            Err(_) => Operand::Value(text.to_string()),
+        },
+        _ => Operand::Value(text.to_string()),
+    }
+}
+
+fn is_long(text: &str) -> bool {
+    matches!(operand(text), Operand::Long(_))
+}
+
+fn encode(program: &Program, mnemonic: &str, operands: &[String], location: &Location) -> Result<Instruction, AsmError> {
+    use Operand::*;
+
+    let ops: Vec<Operand> = operands.iter().map(|text| operand(text)).collect();
+
+    let addr = |text: &str| -> Result<Address, AsmError> {
+        let value = evaluate(program, text, location)?;
+        if (0..=0xFFF).contains(&value) {
+            Ok(Address::from(value as u16))
+        } else {
+            Err(location.error(format!("{:#X} is not a 12 bit address", value)))
+        }
+    };
+    let byte = |text: &str| to_byte(evaluate(program, text, location)?, location);
+    let nibble = |text: &str| -> Result<u8, AsmError> {
+        let value = evaluate(program, text, location)?;
+        if (0..=0xF).contains(&value) {
+            Ok(value as u8)
+        } else {
+            Err(location.error(format!("{} does not fit in a nibble", value)))
+        }
+    };
+
+    let instruction = match (mnemonic, ops.as_slice()) {
+        ("halt", []) => Instruction::Halt,
+        ("cls", []) => Instruction::Clear,
+        ("ret", []) => Instruction::Return,
+        ("scd", [Value(n)]) => Instruction::ScrollDown { n: nibble(n)? },
+        ("scu", [Value(n)]) => Instruction::ScrollUp { n: nibble(n)? },
+        ("scr", []) => Instruction::ScrollRight,
+        ("scl", []) => Instruction::ScrollLeft,
+        ("exit", []) => Instruction::Exit,
+        ("low", []) => Instruction::LowRes,
+        ("high", []) => Instruction::HighRes,
+        ("audio", []) => Instruction::LoadAudioPattern,
+        ("sys", [Value(a)]) => Instruction::CallRoutine { nnn: addr(a)? },
+        ("jp", [Value(a)]) => Instruction::Goto { nnn: addr(a)? },
+        ("jp", [Register(0), Value(a)]) => Instruction::JumpToNNNPlusV0 { nnn: addr(a)? },
+        ("call", [Value(a)]) => Instruction::Call { nnn: addr(a)? },
+        ("se", [Register(x), Register(y)]) => Instruction::SkipXEqY { x: *x, y: *y },
+        ("save", [Register(x), Register(y)]) => Instruction::StoreRange { x: *x, y: *y },
+        ("load", [Register(x), Register(y)]) => Instruction::LoadRange { x: *x, y: *y },
+        ("plane", [Value(n)]) => Instruction::SelectPlanes { n: nibble(n)? },
+        ("se", [Register(x), Value(b)]) => Instruction::SkipXEqNN { x: *x, nn: byte(b)? },
+        ("sne", [Register(x), Register(y)]) => Instruction::SkipXNeqY { x: *x, y: *y },
+        ("sne", [Register(x), Value(b)]) => Instruction::SkipXNeqNN { x: *x, nn: byte(b)? },
+        ("ld", [Register(x), Register(y)]) => Instruction::SetXToY { x: *x, y: *y },
+        ("ld", [Register(x), DelayTimer]) => Instruction::SetXToTimer { x: *x },
+        ("ld", [Register(x), Key]) => Instruction::AwaitKey { x: *x },
+        ("ld", [Register(x), IndirectI]) => Instruction::LoadRegisters { x: *x },
+        ("ld", [Register(x), Flags]) => Instruction::LoadFlags { x: *x },
+        ("ld", [Register(x), Value(b)]) => Instruction::SetXToNN { x: *x, nn: byte(b)? },
+        ("ld", [I, Value(a)]) => Instruction::SetIToNNN { nnn: addr(a)? },
+        ("ld", [I, Long(_)]) => Instruction::SetIToLong,
+        ("ld", [DelayTimer, Register(x)]) => Instruction::SetTimerToX { x: *x },
+        ("ld", [SoundTimer, Register(x)]) => Instruction::SetSoundTimer { x: *x },
+        ("ld", [Font, Register(x)]) => Instruction::SetIToSpriteAddr { x: *x },
+        ("ld", [LargeFont, Register(x)]) => Instruction::SetIToLargeSpriteAddr { x: *x },
+        ("ld", [Bcd, Register(x)]) => Instruction::StoreBcd { x: *x },
+        ("ld", [Pitch, Register(x)]) => Instruction::SetPitch { x: *x },
+        ("ld", [IndirectI, Register(x)]) => Instruction::StoreRegisters { x: *x },
+        ("ld", [Flags, Register(x)]) => Instruction::SaveFlags { x: *x },
+        ("add", [Register(x), Register(y)]) => Instruction::AddXY { x: *x, y: *y },
+        ("add", [Register(x), Value(b)]) => Instruction::AddNNToX { x: *x, nn: byte(b)? },
+        ("add", [I, Register(x)]) => Instruction::AddXToI { x: *x },
+        ("or", [Register(x), Register(y)]) => Instruction::Or { x: *x, y: *y },
+        ("and", [Register(x), Register(y)]) => Instruction::And { x: *x, y: *y },
+        ("xor", [Register(x), Register(y)]) => Instruction::Xor { x: *x, y: *y },
+        ("sub", [Register(x), Register(y)]) => Instruction::SubXY { x: *x, y: *y },
+        ("subn", [Register(x), Register(y)]) => Instruction::SubYX { x: *x, y: *y },
+        // without a second register the shift reads VX itself
+        ("shr", [Register(x)]) => Instruction::ShiftRight { x: *x, y: *x },
+        ("shr", [Register(x), Register(y)]) => Instruction::ShiftRight { x: *x, y: *y },
+        ("shl", [Register(x)]) => Instruction::ShiftLeft { x: *x, y: *x },
+        ("shl", [Register(x), Register(y)]) => Instruction::ShiftLeft { x: *x, y: *y },
+        ("rnd", [Register(x), Value(b)]) => Instruction::Rand { x: *x, nn: byte(b)? },
+        ("drw", [Register(x), Register(y), Value(n)]) => Instruction::Draw { x: *x, y: *y, n: nibble(n)? },
+        ("skp", [Register(x)]) => Instruction::SkipIfKey { x: *x },
+        ("sknp", [Register(x)]) => Instruction::SkipIfNotKey { x: *x },
+        // CHIP-8X
+        ("bgc", []) => Instruction::CycleBackground,
+        ("col", [Register(x), Register(y)]) => Instruction::SetZoneColour { x: *x, y: *y },
+        ("col", [Register(x), Register(y), Value(n)]) => Instruction::SetRowColour { x: *x, y: *y, n: nibble(n)? },
+        ("skp2", [Register(x)]) => Instruction::SkipIfKey2 { x: *x },
+        ("sknp2", [Register(x)]) => Instruction::SkipIfNotKey2 { x: *x },
+        _ => {
+            return Err(location.error(format!(
+                "cannot assemble '{} {}'",
+                mnemonic.to_ascii_uppercase(),
+                operands.join(", ")
+            )))
+        }
+    };
+
+    Ok(instruction)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+// Splits off a leading `label:`
+fn split_label(line: &str) -> (Option<&str>, &str) {
+    let trimmed = line.trim();
+    let (word, rest) = split_word(trimmed);
+
+    match word.strip_suffix(':') {
+        Some(label) => (Some(label), rest),
+        None => (None, trimmed),
+    }
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    }
+}
+
+// Splits on commas that are outside of quotes and brackets
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut depth = 0;
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '[' if !quoted => depth += 1,
+            ')' | ']' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                operands.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_string());
+    }
+
+    operands
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    let text = text.trim();
+    text.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+}
@@ -0,0 +1,154 @@
+// Renders the buzzer to 16 bit mono PCM: a square wave, or the XO-CHIP audio pattern once
+// one is loaded, for every frame the sound timer is non-zero and silence otherwise, so sound
+// can be checked without an audio device.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use super::timers::TIMER_FREQUENCY;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+pub const DEFAULT_TONE: u32 = 440;
+pub const DEFAULT_VOLUME: i16 = 8_192;
+
+// XO-CHIP's audio pattern is 16 bytes, played a bit at a time from the most significant
+pub const PATTERN_LENGTH: usize = 16;
+pub const PATTERN_BITS: usize = PATTERN_LENGTH * 8;
+pub const DEFAULT_PITCH: u8 = 64;
+
+// The XO-CHIP audio registers, loaded by F002 and FX3A. Until a pattern is loaded the
+// buzzer plays the plain square wave tone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Voice {
+    pub pattern: Option<[u8; PATTERN_LENGTH]>,
+    pub pitch: u8,
+}
+
+impl Voice {
+    // Pattern bits played per second: 4000 * 2 ^ ((pitch - 64) / 48)
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2_f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Self { pattern: None, pitch: DEFAULT_PITCH }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audio {
+    pub sample_rate: u32,
+    // Pitch of the square wave in Hz
+    pub tone: u32,
+    // Peak amplitude of the wave
+    pub volume: i16,
+    samples: Vec<i16>,
+    // Position within the wave's period, in units of 1 / sample_rate of a period
+    phase: u64,
+    // Position within the XO-CHIP pattern, in bits
+    bit: f64,
+    // Sample rates that do not divide evenly into frames carry the rest to the next one
+    remainder: u32,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Audio {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            tone: DEFAULT_TONE,
+            volume: DEFAULT_VOLUME,
+            samples: Vec::new(),
+            phase: 0,
+            bit: 0.0,
+            remainder: 0,
+        }
+    }
+
+    // Appends one 60 Hz frame of samples, playing the voice's pattern if it has one and the
+    // square wave tone if not. Every beep starts at the beginning of the wave or pattern.
+    pub fn render_frame(&mut self, sounding: bool, voice: &Voice) {
+        let total = self.sample_rate + self.remainder;
+        let count = total / TIMER_FREQUENCY;
+        self.remainder = total % TIMER_FREQUENCY;
+
+        if !sounding {
+            self.phase = 0;
+            self.bit = 0.0;
+            self.samples.extend(std::iter::repeat_n(0, count as usize));
+            return;
+        }
+
+        let step = voice.playback_rate() / self.sample_rate as f64;
+        for _ in 0..count {
+            let high = match &voice.pattern {
+                Some(pattern) => {
+                    let bit = self.bit as usize % PATTERN_BITS;
+                    self.bit = (self.bit + step) % PATTERN_BITS as f64;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+                }
+                None => {
+                    let high = self.phase * 2 < self.sample_rate as u64;
+                    self.phase = (self.phase + self.tone as u64) % self.sample_rate as u64;
+                    high
+                }
+            };
+            self.samples.push(if high { self.volume } else { self.volume.saturating_neg() });
+        }
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    // Hands over the samples rendered so far, leaving the buffer empty
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+
+    pub fn wav(&self) -> Vec<u8> {
+        wav(&self.samples, self.sample_rate)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.wav())
+    }
+}
+
+// A 16 bit mono PCM WAV file
+pub fn wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    const CHANNELS: u16 = 1;
+    const BITS: u16 = 16;
+    let block_align = CHANNELS * BITS / 8;
+    let data_length = (samples.len() * block_align as usize) as u32;
+
+    let mut wav = Vec::with_capacity(44 + data_length as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_length).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16_u32.to_le_bytes());
+    // PCM
+    wav.extend_from_slice(&1_u16.to_le_bytes());
+    wav.extend_from_slice(&CHANNELS.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&BITS.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_length.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    wav
+}
@@ -0,0 +1,256 @@
+// The RCA CDP1802, the processor of the COSMAC VIP. The VIP's CHIP-8 interpreter ran 0NNN
+// by handing the 1802 over to the machine code at NNN, which returned with D4 (SEP R4).
+//
+// Only what a routine can see is modelled: the registers, the flags and memory. Input and
+// output go nowhere, the EF lines read as inactive and IDL does not wait, so routines that
+// poll hardware or need interrupts will not behave as on the VIP.
+
+// How many 1802 instructions a routine may run before it is given up on
+pub const ROUTINE_STEP_LIMIT: u64 = 1_000_000;
+
+// The register the interpreter runs from, selected by the SEP R4 that ends a routine
+pub const RETURN_REGISTER: u8 = 4;
+// The register a routine is entered through
+pub const ROUTINE_REGISTER: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Cdp1802 {
+    pub r: [u16; 16],
+    pub d: u8,
+    pub df: bool,
+    // Which register is the program counter and which the data pointer
+    pub p: u8,
+    pub x: u8,
+    pub t: u8,
+    pub ie: bool,
+    pub q: bool,
+}
+
+impl Cdp1802 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Runs the routine at `address` until it selects R4 as the program counter, returning
+    // how many instructions it took, or None if it did not return within `limit`
+    pub fn call(&mut self, memory: &mut [u8], address: u16, limit: u64) -> Option<u64> {
+        self.r[ROUTINE_REGISTER as usize] = address;
+        self.p = ROUTINE_REGISTER;
+
+        for steps in 1..=limit {
+            self.step(memory);
+            if self.p == RETURN_REGISTER {
+                return Some(steps);
+            }
+        }
+
+        None
+    }
+
+    // Executes one instruction. Addresses wrap around `memory`, as the VIP's partial
+    // address decoding mirrors its RAM.
+    pub fn step(&mut self, memory: &mut [u8]) {
+        let opcode = self.fetch(memory);
+        let n = opcode & 0xF;
+
+        match opcode >> 4 {
+            // IDL waits for DMA or an interrupt, neither of which happens here
+            0x0 if n == 0 => (),
+            0x0 => self.d = read(memory, self.r[n as usize]),
+            0x1 => self.r[n as usize] = self.r[n as usize].wrapping_add(1),
+            0x2 => self.r[n as usize] = self.r[n as usize].wrapping_sub(1),
+            0x3 => self.short_branch(memory, n),
+            0x4 => {
+                self.d = read(memory, self.r[n as usize]);
+                self.r[n as usize] = self.r[n as usize].wrapping_add(1);
+            }
+            0x5 => write(memory, self.r[n as usize], self.d),
+            0x6 => self.input_output(memory, n),
+            0x7 => self.control(memory, n),
+            0x8 => self.d = self.r[n as usize] as u8,
+            0x9 => self.d = (self.r[n as usize] >> 8) as u8,
+            0xA => self.r[n as usize] = self.r[n as usize] & 0xFF00 | self.d as u16,
+            0xB => self.r[n as usize] = self.r[n as usize] & 0x00FF | (self.d as u16) << 8,
+            0xC => self.long_branch(memory, n),
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.arithmetic(memory, n),
+        }
+    }
+
+    fn fetch(&mut self, memory: &[u8]) -> u8 {
+        let pc = self.r[self.p as usize];
+        self.r[self.p as usize] = pc.wrapping_add(1);
+        read(memory, pc)
+    }
+
+    // M(R(X)), the operand of most ALU instructions
+    fn operand(&self, memory: &[u8]) -> u8 {
+        read(memory, self.r[self.x as usize])
+    }
+
+    fn increment_x(&mut self) {
+        self.r[self.x as usize] = self.r[self.x as usize].wrapping_add(1);
+    }
+
+    // The condition tested by 3N and CN branches, before the sense is inverted for N >= 8.
+    // EF1-EF4 are never active.
+    fn condition(&self, n: u8) -> bool {
+        match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            _ => false,
+        }
+    }
+
+    // 3N: a branch within the current page
+    fn short_branch(&mut self, memory: &[u8], n: u8) {
+        let taken = self.condition(n) != (n >= 8);
+        let pc = self.r[self.p as usize];
+
+        self.r[self.p as usize] = if taken {
+            pc & 0xFF00 | read(memory, pc) as u16
+        } else {
+            pc.wrapping_add(1)
+        };
+    }
+
+    // CN: long branches, long skips and NOP
+    fn long_branch(&mut self, memory: &[u8], n: u8) {
+        let pc = self.r[self.p as usize];
+        let skip = |taken: bool| if taken { pc.wrapping_add(2) } else { pc };
+
+        self.r[self.p as usize] = match n {
+            0x4 => pc,
+            0x5 => skip(!self.q),
+            0x6 => skip(self.d != 0),
+            0x7 => skip(!self.df),
+            0xC => skip(self.ie),
+            0xD => skip(self.q),
+            0xE => skip(self.d == 0),
+            0xF => skip(self.df),
+            _ if self.condition(n) != (n >= 8) => {
+                (read(memory, pc) as u16) << 8 | read(memory, pc.wrapping_add(1)) as u16
+            }
+            _ => pc.wrapping_add(2),
+        };
+    }
+
+    // 6N: IRX, OUT 1-7 and INP 1-7. Nothing is connected, so input reads as 0
+    fn input_output(&mut self, memory: &mut [u8], n: u8) {
+        match n {
+            0x0..=0x7 => self.increment_x(),
+            0x8 => (),
+            _ => {
+                self.d = 0;
+                write(memory, self.r[self.x as usize], 0);
+            }
+        }
+    }
+
+    // 7N: returns, stack operations, Q and arithmetic with carry
+    fn control(&mut self, memory: &mut [u8], n: u8) {
+        match n {
+            0x0 | 0x1 => {
+                let xp = self.operand(memory);
+                self.increment_x();
+                self.x = xp >> 4;
+                self.p = xp & 0xF;
+                self.ie = n == 0x0;
+            }
+            0x2 => {
+                self.d = self.operand(memory);
+                self.increment_x();
+            }
+            0x3 => {
+                write(memory, self.r[self.x as usize], self.d);
+                self.r[self.x as usize] = self.r[self.x as usize].wrapping_sub(1);
+            }
+            0x4 => self.add(self.operand(memory), self.df),
+            0x5 => self.subtract(self.operand(memory), self.d, self.df),
+            0x6 => {
+                let carry = self.df;
+                self.df = self.d & 1 != 0;
+                self.d = self.d >> 1 | (carry as u8) << 7;
+            }
+            0x7 => self.subtract(self.d, self.operand(memory), self.df),
+            0x8 => write(memory, self.r[self.x as usize], self.t),
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                write(memory, self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xC => {
+                let value = self.fetch(memory);
+                self.add(value, self.df);
+            }
+            0xD => {
+                let value = self.fetch(memory);
+                self.subtract(value, self.d, self.df);
+            }
+            0xE => {
+                let carry = self.df;
+                self.df = self.d & 0x80 != 0;
+                self.d = self.d << 1 | carry as u8;
+            }
+            _ => {
+                let value = self.fetch(memory);
+                self.subtract(self.d, value, self.df);
+            }
+        }
+    }
+
+    // FN: logic and arithmetic on M(R(X)), or on the next byte for F8-FF (except FE)
+    fn arithmetic(&mut self, memory: &mut [u8], n: u8) {
+        let value = match n {
+            0x6 | 0xE => 0,
+            0x0..=0x7 => self.operand(memory),
+            _ => self.fetch(memory),
+        };
+
+        match n & 0x7 {
+            0x0 => self.d = value,
+            0x1 => self.d |= value,
+            0x2 => self.d &= value,
+            0x3 => self.d ^= value,
+            0x4 => self.add(value, false),
+            0x5 => self.subtract(value, self.d, true),
+            0x6 if n == 0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0x6 => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => self.subtract(self.d, value, true),
+        }
+    }
+
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // DF is set when there is no borrow, and `no_borrow` is the incoming DF
+    fn subtract(&mut self, from: u8, value: u8, no_borrow: bool) {
+        let difference = from as i16 - value as i16 - !no_borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+fn read(memory: &[u8], address: u16) -> u8 {
+    memory[address as usize % memory.len()]
+}
+
+fn write(memory: &mut [u8], address: u16, value: u8) {
+    let len = memory.len();
+    memory[address as usize % len] = value;
+}
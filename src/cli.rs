@@ -0,0 +1,497 @@
+// The `cpu_emulator` command line, kept in the library so it can be driven from tests.
+//
+//     cpu_emulator run rom.ch8 --cycles 1000 --seed 7 --dump-regs
+//     cpu_emulator play rom.ch8
+//     cpu_emulator disasm rom.ch8 --syntax cowgod
+//     cpu_emulator asm game.8o -o game.ch8
+//     cpu_emulator info rom.ch8
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+use super::asm::{Assembler, Rom};
+use super::audio::Audio;
+use super::cdp1802::Cdp1802;
+use super::disasm::{Disassembler, Syntax};
+use super::error::CliError;
+use super::expr;
+use super::octo::Compiler;
+use super::platform::Platform;
+use super::processor::{Run, CPU, VIP_RESERVED};
+use super::rand::{self, Generator};
+use super::recording::Recording;
+use super::screenshot::{Colour, Screenshot};
+use super::terminal::{self, HeldKeys, Input, RawMode, Screen};
+use super::timers::Clock;
+
+pub const USAGE: &str = "\
+usage: cpu_emulator <command> [options]
+
+commands:
+  run <rom>       run a ROM without a display
+      --cycles N      stop after N instructions instead of at the halt instruction,
+                      which must otherwise come within 10000000
+      --frames N      stop after N 60 Hz frames, running the timers
+      --platform NAME vip, chip48, schip, xo-chip or chip8x, setting the memory,
+                      speed, font, quirks and load address to match
+      --routines NAME what 0NNN machine code calls do: 1802 (run on an emulated RCA
+                      1802, the default for vip and chip8x, which reserves
+                      0xEA0-0xFFF), call (the default otherwise), ignore or error
+      --seed S        seed for the random number generator
+      --rng NAME      xorshift, lfsr or mix
+      --start ADDR    load address and entry point (default 0x200, or the platform's)
+      --dump-regs     print the registers afterwards
+      --dump-screen   print the display afterwards
+      --screenshot F  save the display afterwards as a .pbm, .pgm or .png image
+      --scale N       size of a pixel in the screenshot (default 1)
+      --foreground C, --background C   screenshot colours as #RRGGBB
+      --record F      save every frame as an animated GIF, scaled and coloured as above
+      --audio F       save the buzzer as a WAV file (with --frames)
+      --sample-rate N samples per second of the WAV file (default 44100)
+  play <rom>      play a ROM in the terminal, keys 1234/QWER/ASDF/ZXCV, Esc quits
+      --platform NAME, --routines NAME, --seed S, --rng NAME, --start ADDR   as for run
+      --speed N       instructions per 60 Hz frame (default 9, or the platform's)
+      --record F      as for run, Tab pauses and resumes recording
+      --scale N, --foreground C, --background C   as for run
+  disasm <rom>    list a ROM as assembly
+      --syntax NAME   octo or cowgod (default octo)
+      --origin ADDR   load address and entry point (default 0x200, or the platform's)
+      --platform NAME as for run
+  asm <source>    assemble Cowgod assembly, or Octo if the file ends in .8o
+      -o, --output F  where to write the ROM (default: the source with a .ch8 extension)
+      --syntax NAME   octo or cowgod, overriding the file extension
+      --symbols       print the symbol table
+  info <rom>      summarise a ROM
+      --origin ADDR, --platform NAME   as for disasm";
+
+// Runs the command in `args` (without the program name), writing its output to `out`
+pub fn run<W: Write>(args: &[String], out: &mut W) -> Result<(), CliError> {
+    let (command, rest) = args.split_first().ok_or_else(|| usage("no command given"))?;
+
+    let text = match command.as_str() {
+        "run" => run_rom(rest)?,
+        "play" => play(rest)?,
+        "disasm" => disassemble(rest)?,
+        "asm" => assemble(rest)?,
+        "info" => info(rest)?,
+        "help" | "--help" | "-h" => format!("{}\n", USAGE),
+        _ => return Err(usage(format!("unknown command '{}'", command))),
+    };
+
+    out.write_all(text.as_bytes()).map_err(|error| CliError::Io { path: "<output>".to_string(), error })
+}
+
+// How long `run` waits for the halt instruction when given neither --cycles nor --frames
+pub const DEFAULT_CYCLE_LIMIT: usize = 10_000_000;
+
+fn usage<S: Into<String>>(message: S) -> CliError {
+    CliError::Usage(message.into())
+}
+
+// The arguments of a command: positionals, switches that were given, and option values
+struct Arguments {
+    positional: Vec<String>,
+    switches: BTreeSet<String>,
+    options: BTreeMap<String, String>,
+}
+
+impl Arguments {
+    fn parse(args: &[String], switches: &[&str], options: &[&str]) -> Result<Self, CliError> {
+        let mut parsed = Arguments { positional: Vec::new(), switches: BTreeSet::new(), options: BTreeMap::new() };
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                parsed.positional.push(arg.clone());
+                continue;
+            }
+
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+
+            if switches.contains(&name) && inline.is_none() {
+                parsed.switches.insert(name.to_string());
+            } else if options.contains(&name) {
+                let value = match inline {
+                    Some(value) => value,
+                    None => args.next().cloned().ok_or_else(|| usage(format!("{} needs a value", name)))?,
+                };
+                parsed.options.insert(name.to_string(), value);
+            } else {
+                return Err(usage(format!("unknown option '{}'", arg)));
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn file(&self) -> Result<&str, CliError> {
+        match self.positional.as_slice() {
+            [file] => Ok(file),
+            [] => Err(usage("no file given")),
+            _ => Err(usage(format!("unexpected argument '{}'", self.positional[1]))),
+        }
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn number(&self, name: &str) -> Result<Option<u64>, CliError> {
+        match self.option(name) {
+            Some(text) => expr::parse_number(text)
+                .and_then(|value| u64::try_from(value).ok())
+                .map(Some)
+                .ok_or_else(|| usage(format!("{} expects a number, not '{}'", name, text))),
+            None => Ok(None),
+        }
+    }
+
+    // An address inside memory of `size` bytes
+    fn address(&self, name: &str, default: u16, size: usize) -> Result<u16, CliError> {
+        match self.number(name)? {
+            Some(value) if value < size as u64 => Ok(value as u16),
+            Some(value) => Err(usage(format!("{} of {:#X} is outside memory", name, value))),
+            None => Ok(default),
+        }
+    }
+
+    fn platform(&self) -> Result<Option<Platform>, CliError> {
+        self.option("--platform").map(|name| name.parse().map_err(usage)).transpose()
+    }
+
+    fn syntax(&self, default: Syntax) -> Result<Syntax, CliError> {
+        match self.option("--syntax") {
+            Some(name) => name.parse().map_err(usage),
+            None => Ok(default),
+        }
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, CliError> {
+    fs::read(path).map_err(|error| CliError::Io { path: path.to_string(), error })
+}
+
+// A machine with the ROM named in `args` loaded, configured by --platform, --routines,
+// --start, --rng and --seed
+fn load(args: &Arguments) -> Result<CPU, CliError> {
+    let rom = read(args.file()?)?;
+
+    let mut cpu = CPU::new();
+    if let Some(platform) = args.platform()? {
+        cpu.set_platform(platform)?;
+    }
+    match args.option("--routines") {
+        Some("1802") => cpu.cdp1802 = Some(Cdp1802::new()),
+        Some(fallback) => {
+            cpu.cdp1802 = None;
+            cpu.routine_fallback = fallback.parse().map_err(usage)?;
+        }
+        None => (),
+    }
+    cpu.program_start = args.address("--start", cpu.program_start, cpu.memory.len())?;
+
+    let generator: Generator = args.option("--rng").unwrap_or("xorshift").parse().map_err(usage)?;
+    cpu.rng = generator.build(args.number("--seed")?.unwrap_or(rand::DEFAULT_SEED));
+    cpu.load_rom(&rom)?;
+
+    Ok(cpu)
+}
+
+fn run_rom(args: &[String]) -> Result<String, CliError> {
+    let args = Arguments::parse(
+        args,
+        &["--dump-regs", "--dump-screen"],
+        &[
+            "--cycles", "--frames", "--platform", "--routines", "--seed", "--rng", "--start",
+            "--screenshot", "--scale", "--foreground", "--background", "--record", "--audio", "--sample-rate",
+        ],
+    )?;
+    let mut cpu = load(&args)?;
+    if args.option("--audio").is_some() {
+        cpu.audio = Some(match args.number("--sample-rate")? {
+            Some(rate @ 1_000..=192_000) => Audio::new(rate as u32),
+            Some(_) => return Err(usage("--sample-rate must be between 1000 and 192000")),
+            None => Audio::default(),
+        });
+    }
+    let style = screenshot(&args)?;
+    if args.option("--record").is_some() {
+        cpu.start_recording();
+    }
+
+    let mut text = String::new();
+    match (args.number("--cycles")?, args.number("--frames")?) {
+        (Some(_), Some(_)) => return Err(usage("--cycles and --frames cannot be used together")),
+        (None, Some(frames)) => {
+            let mut running = true;
+            while running && cpu.clock.frames < frames {
+                running = cpu.run_frame()?;
+            }
+            let outcome = if running { "stopped" } else { "halted" };
+            writeln!(text, "{} after {} frames", outcome, cpu.clock.frames).unwrap();
+        }
+        (cycles, None) => {
+            let limit = cycles.map_or(DEFAULT_CYCLE_LIMIT, |cycles| cycles as usize);
+            let run = run_cycles(&mut cpu, limit)?;
+            if cycles.is_none() && !run.halted {
+                return Err(CliError::NoHalt { cycles: run.cycles });
+            }
+            let outcome = if run.halted { "halted" } else { "stopped" };
+            writeln!(text, "{} after {} cycles", outcome, run.cycles).unwrap();
+        }
+    }
+
+    if args.switch("--dump-regs") {
+        text.push_str(&registers(&cpu));
+    }
+    if args.switch("--dump-screen") {
+        for row in cpu.display.rows() {
+            let line: String = row.iter().map(|lit| if *lit { '#' } else { '.' }).collect();
+            writeln!(text, "{}", line).unwrap();
+        }
+    }
+    if let Some(path) = args.option("--screenshot") {
+        style.save(&cpu.display, path).map_err(|error| CliError::Io { path: path.to_string(), error })?;
+    }
+    if let (Some(path), Some(recording)) = (args.option("--record"), cpu.stop_recording()) {
+        save_recording(&recording, path, &style)?;
+    }
+    if let (Some(path), Some(audio)) = (args.option("--audio"), &cpu.audio) {
+        audio.save(path).map_err(|error| CliError::Io { path: path.to_string(), error })?;
+    }
+
+    Ok(text)
+}
+
+// Runs up to `limit` instructions, ticking the timers after every frame's worth of them so
+// delays and the buzzer behave as they would with --frames
+fn run_cycles(cpu: &mut CPU, limit: usize) -> Result<Run, CliError> {
+    let per_frame = cpu.clock.instructions_per_frame.max(1) as usize;
+    let mut run = Run { cycles: 0, halted: false };
+
+    while !run.halted && run.cycles < limit {
+        if run.cycles.is_multiple_of(per_frame) {
+            cpu.keypad.update(cpu.clock.frames);
+            cpu.keypad2.update(cpu.clock.frames);
+        }
+
+        run.halted = cpu.step()?.is_halted();
+        run.cycles += 1;
+
+        if run.cycles.is_multiple_of(per_frame) {
+            cpu.tick_timers();
+        }
+    }
+
+    Ok(run)
+}
+
+fn save_recording(recording: &Recording, path: &str, style: &Screenshot) -> Result<(), CliError> {
+    recording.save(path, style).map_err(|error| CliError::Io { path: path.to_string(), error })
+}
+
+fn play(args: &[String]) -> Result<String, CliError> {
+    let args = Arguments::parse(
+        args,
+        &[],
+        &[
+            "--platform", "--routines", "--seed", "--rng", "--start", "--speed",
+            "--record", "--scale", "--foreground", "--background",
+        ],
+    )?;
+    let mut cpu = load(&args)?;
+    if let Some(speed) = args.number("--speed")? {
+        cpu.clock.instructions_per_frame = u32::try_from(speed).map_err(|_| usage("--speed is too large"))?;
+    }
+    let style = screenshot(&args)?;
+    let record = args.option("--record");
+    if record.is_some() {
+        cpu.start_recording();
+    }
+
+    let _raw = RawMode::enable().map_err(terminal_error)?;
+    let mut screen = Screen::new(io::stdout());
+    let mut paused = None;
+
+    screen.begin().map_err(terminal_error)?;
+    let outcome = play_frames(&mut cpu, &mut screen, &mut paused);
+    screen.end().map_err(terminal_error)?;
+
+    if let Some(path) = record {
+        if let Some(recording) = cpu.stop_recording().or(paused) {
+            save_recording(&recording, path, &style)?;
+        }
+    }
+
+    Ok(format!("{} after {} frames\r\n", outcome?, cpu.clock.frames))
+}
+
+fn terminal_error(error: io::Error) -> CliError {
+    CliError::Io { path: "<terminal>".to_string(), error }
+}
+
+// Runs frames in real time until the program halts or the player quits. A recording in
+// progress is moved to `paused` and back as the player toggles it.
+fn play_frames<W: Write>(
+    cpu: &mut CPU,
+    screen: &mut Screen<W>,
+    paused: &mut Option<Recording>,
+) -> Result<&'static str, CliError> {
+    let mut held = HeldKeys::default();
+    let mut stdin = io::stdin();
+    let mut buffer = [0; 64];
+
+    loop {
+        let started = Instant::now();
+
+        let read = stdin.read(&mut buffer).map_err(terminal_error)?;
+        let inputs = terminal::decode_input(&buffer[..read]);
+        if inputs.contains(&Input::Quit) {
+            return Ok("quit");
+        }
+        for input in inputs {
+            match input {
+                Input::Key(key) => held.press(&mut cpu.keypad, key, cpu.clock.frames),
+                Input::Record if cpu.recording.is_some() => *paused = cpu.recording.take(),
+                Input::Record => cpu.recording = paused.take(),
+                Input::Quit => {}
+            }
+        }
+        held.update(&mut cpu.keypad, cpu.clock.frames);
+
+        let running = cpu.run_frame()?;
+        screen.draw(&cpu.display).map_err(terminal_error)?;
+        if !running {
+            return Ok("halted");
+        }
+
+        if let Some(rest) = Clock::frame_duration().checked_sub(started.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+}
+
+fn screenshot(args: &Arguments) -> Result<Screenshot, CliError> {
+    let mut screenshot = Screenshot::default();
+
+    if let Some(scale) = args.number("--scale")? {
+        screenshot.scale = match scale {
+            1..=64 => scale as usize,
+            _ => return Err(usage("--scale must be between 1 and 64")),
+        };
+    }
+    if let Some(colour) = args.option("--foreground") {
+        screenshot.foreground = colour.parse::<Colour>().map_err(usage)?;
+    }
+    if let Some(colour) = args.option("--background") {
+        screenshot.background = colour.parse::<Colour>().map_err(usage)?;
+    }
+
+    Ok(screenshot)
+}
+
+fn registers(cpu: &CPU) -> String {
+    let mut text = String::new();
+
+    for (row, values) in cpu.registers.chunks(8).enumerate() {
+        let fields: Vec<String> = values.iter().enumerate()
+            .map(|(index, value)| format!("V{:X}={:02X}", row * 8 + index, value))
+            .collect();
+        writeln!(text, "{}", fields.join(" ")).unwrap();
+    }
+
+    writeln!(
+        text,
+        "I={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}",
+        cpu.i, u16::from(cpu.program_counter), cpu.stack_pointer, cpu.timers.delay, cpu.timers.sound,
+    ).unwrap();
+
+    text
+}
+
+// Loading at the platform's program start unless --origin says otherwise
+fn disassembler(args: &Arguments, syntax: Syntax) -> Result<Disassembler, CliError> {
+    let platform = args.platform()?.unwrap_or_default();
+    let mut disassembler = Disassembler::new(syntax);
+    disassembler.mode = platform.mode();
+    disassembler.origin = args.address("--origin", platform.program_start(), platform.memory_size())?;
+    disassembler.entry = disassembler.origin;
+
+    Ok(disassembler)
+}
+
+fn disassemble(args: &[String]) -> Result<String, CliError> {
+    let args = Arguments::parse(args, &[], &["--syntax", "--origin", "--platform"])?;
+    let rom = read(args.file()?)?;
+
+    Ok(disassembler(&args, args.syntax(Syntax::Octo)?)?.disassemble(&rom).to_string())
+}
+
+fn assemble(args: &[String]) -> Result<String, CliError> {
+    let args = Arguments::parse(args, &["--symbols"], &["-o", "--output", "--syntax"])?;
+    let source = Path::new(args.file()?);
+
+    let by_extension = if source.extension().is_some_and(|ext| ext == "8o") { Syntax::Octo } else { Syntax::Cowgod };
+    let rom: Rom = match args.syntax(by_extension)? {
+        Syntax::Octo => Compiler::default().compile_file(source)?,
+        Syntax::Cowgod => Assembler::default().assemble_file(source)?,
+    };
+
+    let output = args.option("-o").or_else(|| args.option("--output"))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| source.with_extension("ch8"));
+    fs::write(&output, &rom.bytes).map_err(|error| CliError::Io { path: output.display().to_string(), error })?;
+
+    let mut text = format!("wrote {} bytes to {}\n", rom.bytes.len(), output.display());
+    if args.switch("--symbols") {
+        text.push_str(&rom.to_string());
+    }
+
+    Ok(text)
+}
+
+fn info(args: &[String]) -> Result<String, CliError> {
+    let args = Arguments::parse(args, &[], &["--origin", "--platform"])?;
+    let path = args.file()?;
+    let rom = read(path)?;
+
+    let disassembler = disassembler(&args, Syntax::Octo)?;
+    let listing = disassembler.disassemble(&rom);
+    let instructions = listing.code().count();
+    let code_bytes: usize = listing.code().map(|line| line.bytes.len()).sum();
+    // As `run` does, only a platform asked for by name brings an 1802 and its work area
+    let platform = args.platform()?;
+    let end = match platform {
+        Some(platform) if platform.has_cdp1802() && (disassembler.origin as usize) < VIP_RESERVED.end => VIP_RESERVED.start,
+        _ => platform.unwrap_or_default().memory_size(),
+    };
+    let available = end.saturating_sub(disassembler.origin as usize);
+
+    let mut text = String::new();
+    writeln!(text, "file: {}", path).unwrap();
+    writeln!(text, "size: {} bytes", rom.len()).unwrap();
+    if !rom.is_empty() {
+        let end = disassembler.origin as usize + rom.len() - 1;
+        writeln!(text, "range: {:03X}-{:03X}", disassembler.origin, end).unwrap();
+    }
+    match available.checked_sub(rom.len()) {
+        Some(free) => writeln!(text, "free: {} bytes", free).unwrap(),
+        None => writeln!(text, "too large by {} bytes", rom.len() - available).unwrap(),
+    }
+    writeln!(text, "instructions: {}", instructions).unwrap();
+    writeln!(text, "data: {} bytes", rom.len() - code_bytes).unwrap();
+
+    Ok(text)
+}
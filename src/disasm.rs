@@ -0,0 +1,378 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use super::address::Address;
+use super::instruction::Instruction;
+use super::opcodes::OPCODELENGTH;
+use super::processor::{Mode, PROGRAM_START};
+
+pub const DEFAULT_ORIGIN: u16 = PROGRAM_START;
+
+// How many unreachable bytes are grouped onto a single data line
+const DATA_PER_LINE: usize = 8;
+
+// Addresses are 16 bits, so nothing past 0xFFFF is listed
+pub const ADDRESS_SPACE: usize = 0x10000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    // Octo's statement syntax, e.g. `v1 += v2`
+    Octo,
+    // The mnemonics from Cowgod's technical reference, e.g. `ADD V1, V2`
+    Cowgod,
+}
+
+impl std::str::FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "octo" => Ok(Syntax::Octo),
+            "cowgod" => Ok(Syntax::Cowgod),
+            _ => Err(format!("unknown syntax '{}'", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Code(Instruction),
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub entry: Entry,
+}
+
+impl Line {
+    pub fn text(&self, syntax: Syntax) -> String {
+        match &self.entry {
+            // F000 is followed by its 16 bit address
+            Entry::Code(Instruction::SetIToLong) if self.bytes.len() == 4 => {
+                let nnnn = (self.bytes[2] as u16) << 8 | self.bytes[3] as u16;
+                match syntax {
+                    Syntax::Octo => format!("{} 0x{:04X}", mnemonic(&Instruction::SetIToLong, syntax), nnnn),
+                    Syntax::Cowgod => format!("{} #{:04X}", mnemonic(&Instruction::SetIToLong, syntax), nnnn),
+                }
+            }
+            Entry::Code(instruction) => mnemonic(instruction, syntax),
+            Entry::Data => data(&self.bytes, syntax),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    pub syntax: Syntax,
+    pub lines: Vec<Line>,
+}
+
+impl Listing {
+    pub fn code(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter().filter(|line| line.entry != Entry::Data)
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            let raw: String = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            writeln!(f, "{:04X}  {:<16}  {}", line.address, raw, line.text(self.syntax))?;
+        }
+
+        Ok(())
+    }
+}
+
+// Disassembles bytes loaded at `origin`. Only what can be reached by following jumps,
+// calls and skips from `entry` is treated as code, everything else is listed as data.
+// Bytes that would lie beyond the end of the 16 bit address space are left out. Words
+// are decoded as `mode` reads them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disassembler {
+    pub syntax: Syntax,
+    pub mode: Mode,
+    pub origin: u16,
+    pub entry: u16,
+}
+
+impl Disassembler {
+    pub fn new(syntax: Syntax) -> Self {
+        Self {
+            syntax,
+            mode: Mode::default(),
+            origin: DEFAULT_ORIGIN,
+            entry: DEFAULT_ORIGIN,
+        }
+    }
+
+    pub fn disassemble_file<P: AsRef<Path>>(&self, path: P) -> io::Result<Listing> {
+        Ok(self.disassemble(&fs::read(path)?))
+    }
+
+    pub fn disassemble(&self, bytes: &[u8]) -> Listing {
+        let bytes = &bytes[..bytes.len().min(ADDRESS_SPACE - self.origin as usize)];
+        let code = self.trace(bytes);
+        let mut lines = Vec::new();
+        let mut offset = 0;
+
+        while offset < bytes.len() {
+            let address = self.origin + offset as u16;
+
+            if code.contains(&address) {
+                let word = (bytes[offset] as u16) << 8 | bytes[offset + 1] as u16;
+                let length = instruction_length(self.mode, word);
+
+                lines.push(Line {
+                    address,
+                    bytes: bytes[offset..offset + length].to_vec(),
+                    entry: Entry::Code(self.mode.decode(word).unwrap()),
+                });
+                offset += length;
+                continue;
+            }
+
+            let start = offset;
+            while offset < bytes.len()
+                && offset - start < DATA_PER_LINE
+                && !code.contains(&(self.origin + offset as u16))
+            {
+                offset += 1;
+            }
+
+            lines.push(Line {
+                address,
+                bytes: bytes[start..offset].to_vec(),
+                entry: Entry::Data,
+            });
+        }
+
+        Listing { syntax: self.syntax, lines }
+    }
+
+    // Addresses of every instruction reachable from the entry point
+    fn trace(&self, bytes: &[u8]) -> BTreeSet<u16> {
+        let mut code = BTreeSet::new();
+        let mut pending = vec![self.entry];
+
+        while let Some(address) = pending.pop() {
+            if address < self.origin || code.contains(&address) {
+                continue;
+            }
+
+            let offset = (address - self.origin) as usize;
+            let word = match self.word_at(bytes, address) {
+                Some(word) => word,
+                None => continue,
+            };
+            let instruction = match self.mode.decode(word) {
+                Ok(instruction) => instruction,
+                Err(_) => continue,
+            };
+            // an F000 cut off from its address is not code
+            if offset + instruction_length(self.mode, word) > bytes.len() {
+                continue;
+            }
+
+            code.insert(address);
+            let length = |address: u16| {
+                self.word_at(bytes, address).map_or(OPCODELENGTH, |word| instruction_length(self.mode, word)) as u16
+            };
+            pending.extend(successors(address, &instruction, length));
+        }
+
+        code
+    }
+
+    fn word_at(&self, bytes: &[u8], address: u16) -> Option<u16> {
+        let offset = address.checked_sub(self.origin)? as usize;
+        let pair = bytes.get(offset..offset + OPCODELENGTH)?;
+
+        Some((pair[0] as u16) << 8 | pair[1] as u16)
+    }
+}
+
+// Bytes taken by the instruction starting with `word`
+fn instruction_length(mode: Mode, word: u16) -> usize {
+    match mode.decode(word) {
+        Ok(Instruction::SetIToLong) => 2 * OPCODELENGTH,
+        _ => OPCODELENGTH,
+    }
+}
+
+// Where execution can continue after the instruction at `address`, given the length of
+// the instruction at any address. Addresses wrap around at the end of the 16 bit space.
+fn successors<L: Fn(u16) -> u16>(address: u16, instruction: &Instruction, length: L) -> Vec<u16> {
+    let next = address.wrapping_add(length(address));
+    let target = |nnn: &Address| u16::from(*nnn);
+
+    match instruction {
+        Instruction::Halt | Instruction::Exit | Instruction::Return => vec![],
+        // the target depends on V0 at runtime
+        Instruction::JumpToNNNPlusV0 { .. } => vec![],
+        Instruction::Goto { nnn } => vec![target(nnn)],
+        Instruction::Call { nnn } => vec![next, target(nnn)],
+        // 0NNN runs native machine code, which is data as far as CHIP-8 is concerned
+        Instruction::CallRoutine { .. } => vec![next],
+        Instruction::SkipXEqNN { .. }
+        | Instruction::SkipXNeqNN { .. }
+        | Instruction::SkipXEqY { .. }
+        | Instruction::SkipXNeqY { .. }
+        | Instruction::SkipIfKey { .. }
+        | Instruction::SkipIfNotKey { .. }
+        | Instruction::SkipIfKey2 { .. }
+        | Instruction::SkipIfNotKey2 { .. } => vec![next, next.wrapping_add(length(next))],
+        _ => vec![next],
+    }
+}
+
+fn data(bytes: &[u8], syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Octo => bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect::<Vec<_>>().join(" "),
+        Syntax::Cowgod => format!("DB {}", bytes.iter().map(|byte| format!("#{:02X}", byte)).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+pub fn mnemonic(instruction: &Instruction, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Octo => octo(instruction),
+        Syntax::Cowgod => cowgod(instruction),
+    }
+}
+
+fn octo(instruction: &Instruction) -> String {
+    let addr = |nnn: &Address| format!("0x{:03X}", u16::from(*nnn));
+
+    match instruction {
+        // Octo has no spelling for these, so they are written out as raw bytes
+        Instruction::Halt
+        | Instruction::CallRoutine { .. }
+        | Instruction::CycleBackground
+        | Instruction::SetZoneColour { .. }
+        | Instruction::SetRowColour { .. }
+        | Instruction::SkipIfKey2 { .. }
+        | Instruction::SkipIfNotKey2 { .. } => {
+            let word = instruction.encode();
+            data(&[(word >> 8) as u8, word as u8], Syntax::Octo)
+        }
+        Instruction::ScrollDown { n } => format!("scroll-down 0x{:X}", n),
+        Instruction::ScrollUp { n } => format!("scroll-up 0x{:X}", n),
+        Instruction::Clear => "clear".to_string(),
+        Instruction::Return => "return".to_string(),
+        Instruction::ScrollRight => "scroll-right".to_string(),
+        Instruction::ScrollLeft => "scroll-left".to_string(),
+        Instruction::Exit => "exit".to_string(),
+        Instruction::LowRes => "lores".to_string(),
+        Instruction::HighRes => "hires".to_string(),
+        Instruction::Goto { nnn } => format!("jump {}", addr(nnn)),
+        Instruction::Call { nnn } => format!(":call {}", addr(nnn)),
+        Instruction::SkipXEqNN { x, nn } => format!("if v{:X} != 0x{:02X} then", x, nn),
+        Instruction::SkipXNeqNN { x, nn } => format!("if v{:X} == 0x{:02X} then", x, nn),
+        Instruction::SkipXEqY { x, y } => format!("if v{:X} != v{:X} then", x, y),
+        Instruction::StoreRange { x, y } => format!("save v{:X} - v{:X}", x, y),
+        Instruction::LoadRange { x, y } => format!("load v{:X} - v{:X}", x, y),
+        Instruction::SetXToNN { x, nn } => format!("v{:X} := 0x{:02X}", x, nn),
+        Instruction::AddNNToX { x, nn } => format!("v{:X} += 0x{:02X}", x, nn),
+        Instruction::SetXToY { x, y } => format!("v{:X} := v{:X}", x, y),
+        Instruction::Or { x, y } => format!("v{:X} |= v{:X}", x, y),
+        Instruction::And { x, y } => format!("v{:X} &= v{:X}", x, y),
+        Instruction::Xor { x, y } => format!("v{:X} ^= v{:X}", x, y),
+        Instruction::AddXY { x, y } => format!("v{:X} += v{:X}", x, y),
+        Instruction::SubXY { x, y } => format!("v{:X} -= v{:X}", x, y),
+        Instruction::ShiftRight { x, y } => format!("v{:X} >>= v{:X}", x, y),
+        Instruction::SubYX { x, y } => format!("v{:X} =- v{:X}", x, y),
+        Instruction::ShiftLeft { x, y } => format!("v{:X} <<= v{:X}", x, y),
+        Instruction::SkipXNeqY { x, y } => format!("if v{:X} == v{:X} then", x, y),
+        Instruction::SetIToNNN { nnn } => format!("i := {}", addr(nnn)),
+        Instruction::JumpToNNNPlusV0 { nnn } => format!("jump0 {}", addr(nnn)),
+        Instruction::Rand { x, nn } => format!("v{:X} := random 0x{:02X}", x, nn),
+        Instruction::Draw { x, y, n } => format!("sprite v{:X} v{:X} 0x{:X}", x, y, n),
+        Instruction::SkipIfKey { x } => format!("if v{:X} -key then", x),
+        Instruction::SkipIfNotKey { x } => format!("if v{:X} key then", x),
+        Instruction::SetIToLong => "i := long".to_string(),
+        Instruction::SelectPlanes { n } => format!("plane {}", n),
+        Instruction::LoadAudioPattern => "audio".to_string(),
+        Instruction::SetXToTimer { x } => format!("v{:X} := delay", x),
+        Instruction::AwaitKey { x } => format!("v{:X} := key", x),
+        Instruction::SetTimerToX { x } => format!("delay := v{:X}", x),
+        Instruction::SetSoundTimer { x } => format!("buzzer := v{:X}", x),
+        Instruction::AddXToI { x } => format!("i += v{:X}", x),
+        Instruction::SetIToSpriteAddr { x } => format!("i := hex v{:X}", x),
+        Instruction::SetIToLargeSpriteAddr { x } => format!("i := bighex v{:X}", x),
+        Instruction::StoreBcd { x } => format!("bcd v{:X}", x),
+        Instruction::SetPitch { x } => format!("pitch := v{:X}", x),
+        Instruction::StoreRegisters { x } => format!("save v{:X}", x),
+        Instruction::LoadRegisters { x } => format!("load v{:X}", x),
+        Instruction::SaveFlags { x } => format!("saveflags v{:X}", x),
+        Instruction::LoadFlags { x } => format!("loadflags v{:X}", x),
+    }
+}
+
+fn cowgod(instruction: &Instruction) -> String {
+    let addr = |nnn: &Address| format!("#{:03X}", u16::from(*nnn));
+
+    match instruction {
+        Instruction::Halt => "HALT".to_string(),
+        Instruction::CallRoutine { nnn } => format!("SYS {}", addr(nnn)),
+        Instruction::CycleBackground => "BGC".to_string(),
+        Instruction::ScrollDown { n } => format!("SCD #{:X}", n),
+        Instruction::ScrollUp { n } => format!("SCU #{:X}", n),
+        Instruction::Clear => "CLS".to_string(),
+        Instruction::Return => "RET".to_string(),
+        Instruction::ScrollRight => "SCR".to_string(),
+        Instruction::ScrollLeft => "SCL".to_string(),
+        Instruction::Exit => "EXIT".to_string(),
+        Instruction::LowRes => "LOW".to_string(),
+        Instruction::HighRes => "HIGH".to_string(),
+        Instruction::Goto { nnn } => format!("JP {}", addr(nnn)),
+        Instruction::Call { nnn } => format!("CALL {}", addr(nnn)),
+        Instruction::SkipXEqNN { x, nn } => format!("SE V{:X}, #{:02X}", x, nn),
+        Instruction::SkipXNeqNN { x, nn } => format!("SNE V{:X}, #{:02X}", x, nn),
+        Instruction::SkipXEqY { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::StoreRange { x, y } => format!("SAVE V{:X}, V{:X}", x, y),
+        Instruction::LoadRange { x, y } => format!("LOAD V{:X}, V{:X}", x, y),
+        Instruction::SetXToNN { x, nn } => format!("LD V{:X}, #{:02X}", x, nn),
+        Instruction::AddNNToX { x, nn } => format!("ADD V{:X}, #{:02X}", x, nn),
+        Instruction::SetXToY { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::AddXY { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::SubXY { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::SubYX { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SkipXNeqY { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::SetIToNNN { nnn } => format!("LD I, {}", addr(nnn)),
+        Instruction::JumpToNNNPlusV0 { nnn } => format!("JP V0, {}", addr(nnn)),
+        Instruction::SetZoneColour { x, y } => format!("COL V{:X}, V{:X}", x, y),
+        Instruction::SetRowColour { x, y, n } => format!("COL V{:X}, V{:X}, #{:X}", x, y, n),
+        Instruction::Rand { x, nn } => format!("RND V{:X}, #{:02X}", x, nn),
+        Instruction::Draw { x, y, n } => format!("DRW V{:X}, V{:X}, #{:X}", x, y, n),
+        Instruction::SkipIfKey { x } => format!("SKP V{:X}", x),
+        Instruction::SkipIfNotKey { x } => format!("SKNP V{:X}", x),
+        Instruction::SkipIfKey2 { x } => format!("SKP2 V{:X}", x),
+        Instruction::SkipIfNotKey2 { x } => format!("SKNP2 V{:X}", x),
+        Instruction::SetIToLong => "LD I, LONG".to_string(),
+        Instruction::SelectPlanes { n } => format!("PLANE {}", n),
+        Instruction::LoadAudioPattern => "AUDIO".to_string(),
+        Instruction::SetXToTimer { x } => format!("LD V{:X}, DT", x),
+        Instruction::AwaitKey { x } => format!("LD V{:X}, K", x),
+        Instruction::SetTimerToX { x } => format!("LD DT, V{:X}", x),
+        Instruction::SetSoundTimer { x } => format!("LD ST, V{:X}", x),
+        Instruction::AddXToI { x } => format!("ADD I, V{:X}", x),
+        Instruction::SetIToSpriteAddr { x } => format!("LD F, V{:X}", x),
+        Instruction::SetIToLargeSpriteAddr { x } => format!("LD HF, V{:X}", x),
+        Instruction::StoreBcd { x } => format!("LD B, V{:X}", x),
+        Instruction::SetPitch { x } => format!("LD PITCH, V{:X}", x),
+        Instruction::StoreRegisters { x } => format!("LD [I], V{:X}", x),
+        Instruction::LoadRegisters { x } => format!("LD V{:X}, [I]", x),
+        Instruction::SaveFlags { x } => format!("LD R, V{:X}", x),
+        Instruction::LoadFlags { x } => format!("LD V{:X}, R", x),
+    }
+}
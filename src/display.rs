@@ -0,0 +1,254 @@
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+// The SUPER-CHIP high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// Sprites are 8 pixels wide, one bit per pixel with the most significant bit leftmost.
+// SUPER-CHIP's large sprites are 16 pixels wide, two bytes per row.
+pub const SPRITE_WIDTH: usize = 8;
+pub const LARGE_SPRITE_WIDTH: usize = 16;
+
+// What happens to the parts of a sprite that fall off the edge of the screen.
+// The starting coordinate always wraps, this only affects the pixels drawn past it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeMode {
+    Clip,
+    Wrap,
+}
+
+// XO-CHIP draws on two bitplanes, which combine into four colours
+pub const PLANE_COUNT: usize = 2;
+
+// CHIP-8X's colour board gives each zone 8 pixels wide and 1 pixel high its own
+// foreground colour, and the whole screen one background colour. Colours are the board's
+// 3 bit codes, see `COLOUR_NAMES`.
+pub const ZONE_WIDTH: usize = 8;
+pub const COLOUR_NAMES: [&str; 8] = ["black", "red", "blue", "violet", "green", "yellow", "aqua", "white"];
+// 02A0 steps through these background colours in turn
+pub const BACKGROUND_CYCLE: [u8; 4] = [2, 0, 4, 1];
+pub const DEFAULT_FOREGROUND: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColourMap {
+    pub background: u8,
+    columns: usize,
+    zones: Vec<u8>,
+}
+
+impl ColourMap {
+    // Every zone red on a blue background, as the board starts up
+    pub fn new(width: usize, height: usize) -> Self {
+        let columns = width.div_ceil(ZONE_WIDTH);
+
+        Self {
+            background: BACKGROUND_CYCLE[0],
+            columns,
+            zones: vec![DEFAULT_FOREGROUND; columns * height],
+        }
+    }
+
+    // The foreground colour of the zone holding pixel (x, y)
+    pub fn foreground(&self, x: usize, y: usize) -> u8 {
+        self.zones[y * self.columns + x / ZONE_WIDTH]
+    }
+
+    // Colours the zone in `column` (counted in zones) and pixel `row`, wrapping around
+    // the edges of the screen
+    pub fn set_foreground(&mut self, column: usize, row: usize, colour: u8) {
+        let rows = self.zones.len() / self.columns;
+        self.zones[(row % rows) * self.columns + column % self.columns] = colour & 0x7;
+    }
+
+    pub fn cycle_background(&mut self) {
+        let current = BACKGROUND_CYCLE.iter().position(|colour| *colour == self.background).unwrap_or(0);
+        self.background = BACKGROUND_CYCLE[(current + 1) % BACKGROUND_CYCLE.len()];
+    }
+
+    // The colour pixel (x, y) shows
+    pub fn colour(&self, x: usize, y: usize, lit: bool) -> u8 {
+        if lit { self.foreground(x, y) } else { self.background }
+    }
+}
+
+// Framebuffer, stored row by row. Each pixel holds a bit per plane, plane 1 as bit 0.
+// Classic programs only ever touch plane 1, so a pixel counts as lit when it is lit in
+// any plane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Display {
+    pub width: usize,
+    pub height: usize,
+    // Used by `draw_sprite`. The CPU draws with the edge mode its quirks ask for instead
+    pub edge_mode: EdgeMode,
+    // The planes that drawing, clearing and scrolling apply to, as a mask like the pixels
+    pub selected_planes: u8,
+    // CHIP-8X colour attributes, kept beside the monochrome pixels
+    pub colours: Option<ColourMap>,
+    pixels: Vec<u8>,
+}
+
+impl Display {
+    pub fn new(width: usize, height: usize, edge_mode: EdgeMode) -> Self {
+        Self {
+            width,
+            height,
+            edge_mode,
+            selected_planes: 1,
+            colours: None,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    // Clears the selected planes
+    pub fn clear(&mut self) {
+        let keep = !self.selected_planes;
+        self.pixels.iter_mut().for_each(|pixel| *pixel &= keep);
+    }
+
+    // Switches to a `width` x `height` screen, which clears every plane
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width * height];
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_WIDTH && self.height == HIRES_HEIGHT
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.plane_bits(x, y) != 0
+    }
+
+    // The planes the pixel is lit in, which is its colour on XO-CHIP
+    pub fn plane_bits(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    // Lights or clears the pixel in the selected planes
+    pub fn set_pixel(&mut self, x: usize, y: usize, lit: bool) {
+        let pixel = &mut self.pixels[y * self.width + x];
+        if lit {
+            *pixel |= self.selected_planes;
+        } else {
+            *pixel &= !self.selected_planes;
+        }
+    }
+
+    // Whether each pixel is lit, row by row
+    pub fn pixels(&self) -> Vec<bool> {
+        self.pixels.iter().map(|pixel| *pixel != 0).collect()
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = Vec<bool>> + '_ {
+        self.pixels.chunks(self.width).map(|row| row.iter().map(|pixel| *pixel != 0).collect())
+    }
+
+    // The coordinates of the pixels that differ from `other`, row by row. Where the sizes
+    // differ, pixels outside one of the displays count as unlit
+    pub fn diff(&self, other: &Display) -> Vec<(usize, usize)> {
+        let lit = |display: &Display, x: usize, y: usize| x < display.width && y < display.height && display.pixel(x, y);
+        let mut differences = Vec::new();
+
+        for y in 0..self.height.max(other.height) {
+            for x in 0..self.width.max(other.width) {
+                if lit(self, x, y) != lit(other, x, y) {
+                    differences.push((x, y));
+                }
+            }
+        }
+
+        differences
+    }
+
+    // XORs an 8 pixel wide sprite onto the screen at (x, y), one byte per row.
+    // Returns true if any lit pixel was turned off (a collision)
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
+        self.draw_wide_sprite(x, y, sprite, SPRITE_WIDTH, self.edge_mode)
+    }
+
+    // Like `draw_sprite` for a sprite `width` pixels wide, a multiple of 8, with each row
+    // taking `width / 8` bytes, and with the given edge mode. With several planes selected
+    // the sprite holds an image for each of them in turn, plane 1 first, splitting its bytes
+    // evenly.
+    pub fn draw_wide_sprite(&mut self, x: usize, y: usize, sprite: &[u8], width: usize, edge_mode: EdgeMode) -> bool {
+        let x = x % self.width;
+        let y = y % self.height;
+        let planes: Vec<u8> = (0..PLANE_COUNT).map(|plane| 1 << plane).filter(|mask| self.selected_planes & mask != 0).collect();
+        let mut collision = false;
+
+        if planes.is_empty() {
+            return false;
+        }
+
+        let length = (sprite.len() / planes.len()).max(1);
+        for (mask, image) in planes.iter().zip(sprite.chunks(length)) {
+            for (row, bytes) in image.chunks(width / 8).enumerate() {
+                for column in 0..width {
+                    if bytes.get(column / 8).is_none_or(|bits| bits & (0x80 >> (column % 8)) == 0) {
+                        continue;
+                    }
+
+                    if let Some((px, py)) = self.locate(x + column, y + row, edge_mode) {
+                        let index = py * self.width + px;
+                        collision |= self.pixels[index] & mask != 0;
+                        self.pixels[index] ^= mask;
+                    }
+                }
+            }
+        }
+
+        collision
+    }
+
+    // Moves the picture in the selected planes down by `rows`, blanking the rows it uncovers
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.scroll(0, rows as isize);
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.scroll(0, -(rows as isize));
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        self.scroll(-(columns as isize), 0);
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        self.scroll(columns as isize, 0);
+    }
+
+    // Moves every pixel of the selected planes by (dx, dy). Pixels pushed off the screen
+    // are lost, whatever the edge mode.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let moved = self.selected_planes;
+        let mut pixels: Vec<u8> = self.pixels.iter().map(|pixel| pixel & !moved).collect();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (to_x, to_y) = (x as isize + dx, y as isize + dy);
+                if (0..self.width as isize).contains(&to_x) && (0..self.height as isize).contains(&to_y) {
+                    pixels[to_y as usize * self.width + to_x as usize] |= self.plane_bits(x, y) & moved;
+                }
+            }
+        }
+
+        self.pixels = pixels;
+    }
+
+    // Maps a possibly off-screen coordinate onto the screen according to `edge_mode`
+    fn locate(&self, x: usize, y: usize, edge_mode: EdgeMode) -> Option<(usize, usize)> {
+        match edge_mode {
+            EdgeMode::Wrap => Some((x % self.width, y % self.height)),
+            EdgeMode::Clip if x < self.width && y < self.height => Some((x, y)),
+            EdgeMode::Clip => None,
+        }
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new(DISPLAY_WIDTH, DISPLAY_HEIGHT, EdgeMode::Clip)
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+use std::io;
+use super::address::Address;
+
+// Everything that can stop a program short of the halt instruction.
+// `address` is always where the offending instruction was fetched from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuError {
+    StackOverflow { address: Address },
+    StackUnderflow { address: Address },
+    IllegalOpcode { opcode: u16, address: Address },
+    // An access of `length` bytes starting at `location` that runs past the end of memory
+    MemoryFault { location: usize, length: usize },
+    // 0NNN with no 1802 to run the machine code at `routine`, and the fallback set to error
+    UnsupportedRoutine { routine: Address, address: Address },
+    // A machine code routine that did not return within `cdp1802::ROUTINE_STEP_LIMIT` steps
+    RoutineTimeout { routine: Address, address: Address },
+    // A program image of `length` bytes with only `available` bytes free from `start`
+    RomTooLarge { length: usize, start: u16, available: usize },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::StackOverflow { address } => {
+                write!(f, "stack overflow calling from {:03x}", u16::from(*address))
+            }
+            CpuError::StackUnderflow { address } => {
+                write!(f, "stack underflow returning from {:03x}", u16::from(*address))
+            }
+            CpuError::IllegalOpcode { opcode, address } => {
+                write!(f, "illegal opcode {:04x} at {:03x}", opcode, u16::from(*address))
+            }
+            CpuError::MemoryFault { location, length } => {
+                write!(f, "memory fault accessing {} byte(s) at {:04x}", length, location)
+            }
+            CpuError::UnsupportedRoutine { routine, address } => {
+                write!(f, "machine code routine {:03x} called at {:03x} needs an 1802", u16::from(*routine), u16::from(*address))
+            }
+            CpuError::RoutineTimeout { routine, address } => {
+                write!(f, "machine code routine {:03x} called at {:03x} did not return", u16::from(*routine), u16::from(*address))
+            }
+            CpuError::RomTooLarge { length, start, available } => {
+                write!(f, "ROM of {} bytes does not fit in the {} bytes of memory from {:03x}", length, available, start)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+// A word that is not any known instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x} is not a valid instruction", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// A problem in assembler or compiler source, `line` counts from 1 within `file`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// Why a command line invocation failed
+#[derive(Debug)]
+pub enum CliError {
+    // Bad arguments, the usage text is worth showing
+    Usage(String),
+    Io { path: String, error: io::Error },
+    Cpu(CpuError),
+    Asm(AsmError),
+    // A run given no limit that had not halted after the default number of cycles
+    NoHalt { cycles: usize },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::Io { path, error } => write!(f, "{}: {}", path, error),
+            CliError::Cpu(error) => write!(f, "{}", error),
+            CliError::Asm(error) => write!(f, "{}", error),
+            CliError::NoHalt { cycles } => {
+                write!(f, "did not halt within {} cycles, use --cycles or --frames to run for a set time", cycles)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<CpuError> for CliError {
+    fn from(error: CpuError) -> Self {
+        CliError::Cpu(error)
+    }
+}
+
+impl From<AsmError> for CliError {
+    fn from(error: AsmError) -> Self {
+        CliError::Asm(error)
+    }
+}
@@ -0,0 +1,193 @@
+// Integer expressions for the assembler and compiler.
+//
+// Numbers are decimal, hex (`0x1F`, `#1F`, `$1F`) or binary (`0b101`, `%101`). Names are
+// resolved through a callback so the caller decides what a symbol means. Operators from
+// loosest to tightest binding: `|`, `^`, `&`, `<< >>`, `+ -`, `* / %`, then unary `- ~`.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+pub fn evaluate<F>(source: &str, mut lookup: F) -> Result<i64, String>
+where
+    F: FnMut(&str) -> Result<i64, String>,
+{
+    let mut parser = Parser { chars: source.chars().peekable(), lookup: &mut lookup };
+    let value = parser.binary(0)?;
+
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected '{}' in expression '{}'", c, source)),
+    }
+}
+
+// Whether `name` could be a symbol, as opposed to a number or an operator
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        }
+        _ => false,
+    }
+}
+
+pub fn parse_number(text: &str) -> Option<i64> {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(hex) = text.strip_prefix('#').or_else(|| text.strip_prefix('$')) {
+        (hex, 16)
+    } else if let Some(bin) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        (bin, 2)
+    } else if let Some(bin) = text.strip_prefix('%') {
+        (bin, 2)
+    } else {
+        (text, 10)
+    };
+
+    if digits.is_empty() {
+        return None;
+    }
+
+    i64::from_str_radix(&digits.replace('_', ""), radix).ok()
+}
+
+// Binary operators by binding strength, loosest first
+const LEVELS: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser<'a, F> {
+    chars: Peekable<Chars<'a>>,
+    lookup: &'a mut F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: FnMut(&str) -> Result<i64, String>,
+{
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn operator(&mut self, level: usize) -> Option<&'static str> {
+        self.skip_whitespace();
+
+        let first = *self.chars.peek()?;
+        let mut lookahead = self.chars.clone();
+        lookahead.next();
+        let second = lookahead.peek().copied();
+
+        for op in LEVELS[level] {
+            let mut expected = op.chars();
+            let matches = expected.next() == Some(first)
+                && expected.next().is_none_or(|c| Some(c) == second);
+
+            if matches {
+                op.chars().for_each(|_| { self.chars.next(); });
+                return Some(op);
+            }
+        }
+
+        None
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = self.binary(level + 1)?;
+
+        while let Some(op) = self.operator(level) {
+            let right = self.binary(level + 1)?;
+
+            left = match op {
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "<<" => left.checked_shl(right as u32).ok_or("shift out of range")?,
+                ">>" => left.checked_shr(right as u32).ok_or("shift out of range")?,
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" => left.checked_div(right).ok_or("division by zero")?,
+                "%" => left.checked_rem(right).ok_or("division by zero")?,
+                _ => unreachable!(),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        self.skip_whitespace();
+
+        match self.chars.peek() {
+            Some('-') => {
+                self.chars.next();
+                Ok(self.unary()?.wrapping_neg())
+            }
+            Some('~') => {
+                self.chars.next();
+                Ok(!self.unary()?)
+            }
+            Some('(') => {
+                self.chars.next();
+                let value = self.binary(0)?;
+                self.skip_whitespace();
+
+                match self.chars.next() {
+                    Some(')') => Ok(value),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(_) => self.atom(),
+            None => Err("expression ended early".to_string()),
+        }
+    }
+
+    fn atom(&mut self) -> Result<i64, String> {
+        let mut text = String::new();
+
+        // a leading prefix character is part of the number
+        if let Some(c) = self.chars.peek().copied() {
+            if c == '#' || c == '$' || c == '%' {
+                text.push(c);
+                self.chars.next();
+            }
+        }
+
+        while let Some(c) = self.chars.peek().copied() {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                text.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        if text.is_empty() {
+            let found = self.chars.peek().map_or("end of input".to_string(), |c| format!("'{}'", c));
+            return Err(format!("expected a number or name, found {}", found));
+        }
+
+        if let Some(value) = parse_number(&text) {
+            return Ok(value);
+        }
+
+        if is_identifier(&text) {
+            return (self.lookup)(&text);
+        }
+
+        Err(format!("'{}' is not a number", text))
+    }
+}
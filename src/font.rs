@@ -0,0 +1,125 @@
+use super::error::CpuError;
+
+// Where the interpreter keeps its font when nothing else is asked for, inside the
+// reserved area below 0x200
+pub const DEFAULT_FONT_BASE: u16 = 0x050;
+
+pub const SMALL_GLYPH_LENGTH: u16 = 5;
+pub const LARGE_GLYPH_LENGTH: u16 = 10;
+
+// The standard 4x5 hex digits 0-F
+pub const SMALL_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// The SUPER-CHIP 8x10 digits, extended to A-F as XO-CHIP does
+pub const LARGE_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+// A font resident in interpreter memory. The small glyphs are laid out from `base`,
+// the large glyphs (if any) straight after them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub base: u16,
+    pub small: Vec<u8>,
+    pub large: Vec<u8>,
+}
+
+impl Font {
+    pub fn new(base: u16, small: &[u8], large: &[u8]) -> Self {
+        Self {
+            base,
+            small: small.to_vec(),
+            large: large.to_vec(),
+        }
+    }
+
+    // Only the 4x5 font, as the original CHIP-8 interpreter had
+    pub fn chip8(base: u16) -> Self {
+        Self::new(base, &SMALL_FONT, &[])
+    }
+
+    // The 4x5 font together with the SUPER-CHIP 8x10 font
+    pub fn superchip(base: u16) -> Self {
+        Self::new(base, &SMALL_FONT, &LARGE_FONT)
+    }
+
+    pub fn large_base(&self) -> u16 {
+        self.base + self.small.len() as u16
+    }
+
+    // Number of bytes the font occupies in memory
+    pub fn len(&self) -> usize {
+        self.small.len() + self.large.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Copies the glyphs into `memory`, failing without writing anything if they would run
+    // past its end
+    pub fn install(&self, memory: &mut [u8]) -> Result<(), CpuError> {
+        let start = self.base as usize;
+        let area = memory.get_mut(start..start + self.len())
+            .ok_or(CpuError::MemoryFault { location: start, length: self.len() })?;
+        let (small, large) = area.split_at_mut(self.small.len());
+
+        small.copy_from_slice(&self.small);
+        large.copy_from_slice(&self.large);
+        Ok(())
+    }
+
+    // Address of the 4x5 glyph for the low nibble of `character`
+    pub fn small_glyph_address(&self, character: u8) -> u16 {
+        self.base + (character & 0xF) as u16 * SMALL_GLYPH_LENGTH
+    }
+
+    // Address of the 8x10 glyph for the low nibble of `character`, if the font has one
+    pub fn large_glyph_address(&self, character: u8) -> Option<u16> {
+        let offset = (character & 0xF) as u16 * LARGE_GLYPH_LENGTH;
+
+        if offset + LARGE_GLYPH_LENGTH <= self.large.len() as u16 {
+            Some(self.large_base() + offset)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Self::superchip(DEFAULT_FONT_BASE)
+    }
+}
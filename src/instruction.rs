@@ -0,0 +1,228 @@
+use super::address::Address;
+use super::error::DecodeError;
+
+// A decoded instruction. Register operands (x, y) are indices 0-F, `nn` is an 8 bit
+// constant, `n` a 4 bit constant and `nnn` a 12 bit address. Every instruction is one
+// word, except that XO-CHIP's F000 is followed by a second word holding a 16 bit address.
+// CHIP-8X's instructions are only produced by `decode_chip8x`, as BXYN reuses BNNN.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Halt,                                  // 0000
+    CallRoutine { nnn: Address },          // 0NNN
+    CycleBackground,                       // 02A0 (CHIP-8X)
+    ScrollDown { n: u8 },                  // 00CN (SUPER-CHIP)
+    ScrollUp { n: u8 },                    // 00DN (XO-CHIP)
+    Clear,                                 // 00E0
+    Return,                                // 00EE
+    ScrollRight,                           // 00FB (SUPER-CHIP)
+    ScrollLeft,                            // 00FC (SUPER-CHIP)
+    Exit,                                  // 00FD (SUPER-CHIP)
+    LowRes,                                // 00FE (SUPER-CHIP)
+    HighRes,                               // 00FF (SUPER-CHIP)
+    Goto { nnn: Address },                 // 1NNN
+    Call { nnn: Address },                 // 2NNN
+    SkipXEqNN { x: u8, nn: u8 },           // 3XNN
+    SkipXNeqNN { x: u8, nn: u8 },          // 4XNN
+    SkipXEqY { x: u8, y: u8 },             // 5XY0
+    StoreRange { x: u8, y: u8 },           // 5XY2 (XO-CHIP)
+    LoadRange { x: u8, y: u8 },            // 5XY3 (XO-CHIP)
+    SetXToNN { x: u8, nn: u8 },            // 6XNN
+    AddNNToX { x: u8, nn: u8 },            // 7XNN
+    SetXToY { x: u8, y: u8 },              // 8XY0
+    Or { x: u8, y: u8 },                   // 8XY1
+    And { x: u8, y: u8 },                  // 8XY2
+    Xor { x: u8, y: u8 },                  // 8XY3
+    AddXY { x: u8, y: u8 },                // 8XY4
+    SubXY { x: u8, y: u8 },                // 8XY5
+    ShiftRight { x: u8, y: u8 },           // 8XY6
+    SubYX { x: u8, y: u8 },                // 8XY7
+    ShiftLeft { x: u8, y: u8 },            // 8XYE
+    SkipXNeqY { x: u8, y: u8 },            // 9XY0
+    SetIToNNN { nnn: Address },            // ANNN
+    JumpToNNNPlusV0 { nnn: Address },      // BNNN
+    SetZoneColour { x: u8, y: u8 },        // BXY0 (CHIP-8X)
+    SetRowColour { x: u8, y: u8, n: u8 },  // BXYN (CHIP-8X)
+    Rand { x: u8, nn: u8 },                // CXNN
+    Draw { x: u8, y: u8, n: u8 },          // DXYN
+    SkipIfKey { x: u8 },                   // EX9E
+    SkipIfNotKey { x: u8 },                // EXA1
+    SkipIfKey2 { x: u8 },                  // EXF2 (CHIP-8X)
+    SkipIfNotKey2 { x: u8 },               // EXF5 (CHIP-8X)
+    SetIToLong,                            // F000 NNNN (XO-CHIP)
+    SelectPlanes { n: u8 },                // FN01 (XO-CHIP)
+    LoadAudioPattern,                      // F002 (XO-CHIP)
+    SetXToTimer { x: u8 },                 // FX07
+    AwaitKey { x: u8 },                    // FX0A
+    SetTimerToX { x: u8 },                 // FX15
+    SetSoundTimer { x: u8 },               // FX18
+    AddXToI { x: u8 },                     // FX1E
+    SetIToSpriteAddr { x: u8 },            // FX29
+    SetIToLargeSpriteAddr { x: u8 },       // FX30
+    StoreBcd { x: u8 },                    // FX33
+    SetPitch { x: u8 },                    // FX3A (XO-CHIP)
+    StoreRegisters { x: u8 },              // FX55
+    LoadRegisters { x: u8 },               // FX65
+    SaveFlags { x: u8 },                   // FX75 (SUPER-CHIP)
+    LoadFlags { x: u8 },                   // FX85 (SUPER-CHIP)
+}
+
+impl Instruction {
+    pub fn decode(word: u16) -> Result<Self, DecodeError> {
+        let c = (word & 0xF000) >> 12;
+        let x = ((word & 0x0F00) >> 8) as u8;
+        let y = ((word & 0x00F0) >> 4) as u8;
+        let n = (word & 0x000F) as u8;
+        let nn = (word & 0x00FF) as u8;
+        let nnn = Address::from(word & 0x0FFF);
+
+        let instruction = match (c, x, y, n) {
+            (0x0, 0x0, 0x0, 0x0) => Instruction::Halt,
+            (0x0, 0x0, 0xC, _) => Instruction::ScrollDown { n },
+            (0x0, 0x0, 0xD, _) => Instruction::ScrollUp { n },
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Clear,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Return,
+            (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::LowRes,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::HighRes,
+            (0x0, _, _, _) => Instruction::CallRoutine { nnn },
+            (0x1, _, _, _) => Instruction::Goto { nnn },
+            (0x2, _, _, _) => Instruction::Call { nnn },
+            (0x3, _, _, _) => Instruction::SkipXEqNN { x, nn },
+            (0x4, _, _, _) => Instruction::SkipXNeqNN { x, nn },
+            (0x5, _, _, 0x0) => Instruction::SkipXEqY { x, y },
+            (0x5, _, _, 0x2) => Instruction::StoreRange { x, y },
+            (0x5, _, _, 0x3) => Instruction::LoadRange { x, y },
+            (0x6, _, _, _) => Instruction::SetXToNN { x, nn },
+            (0x7, _, _, _) => Instruction::AddNNToX { x, nn },
+            (0x8, _, _, 0x0) => Instruction::SetXToY { x, y },
+            (0x8, _, _, 0x1) => Instruction::Or { x, y },
+            (0x8, _, _, 0x2) => Instruction::And { x, y },
+            (0x8, _, _, 0x3) => Instruction::Xor { x, y },
+            (0x8, _, _, 0x4) => Instruction::AddXY { x, y },
+            (0x8, _, _, 0x5) => Instruction::SubXY { x, y },
+            (0x8, _, _, 0x6) => Instruction::ShiftRight { x, y },
+            (0x8, _, _, 0x7) => Instruction::SubYX { x, y },
+            (0x8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+            (0x9, _, _, 0x0) => Instruction::SkipXNeqY { x, y },
+            (0xA, _, _, _) => Instruction::SetIToNNN { nnn },
+            (0xB, _, _, _) => Instruction::JumpToNNNPlusV0 { nnn },
+            (0xC, _, _, _) => Instruction::Rand { x, nn },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 0x9, 0xE) => Instruction::SkipIfKey { x },
+            (0xE, _, 0xA, 0x1) => Instruction::SkipIfNotKey { x },
+            (0xF, 0x0, 0x0, 0x0) => Instruction::SetIToLong,
+            (0xF, _, 0x0, 0x1) => Instruction::SelectPlanes { n: x },
+            (0xF, 0x0, 0x0, 0x2) => Instruction::LoadAudioPattern,
+            (0xF, _, 0x0, 0x7) => Instruction::SetXToTimer { x },
+            (0xF, _, 0x0, 0xA) => Instruction::AwaitKey { x },
+            (0xF, _, 0x1, 0x5) => Instruction::SetTimerToX { x },
+            (0xF, _, 0x1, 0x8) => Instruction::SetSoundTimer { x },
+            (0xF, _, 0x1, 0xE) => Instruction::AddXToI { x },
+            (0xF, _, 0x2, 0x9) => Instruction::SetIToSpriteAddr { x },
+            (0xF, _, 0x3, 0x0) => Instruction::SetIToLargeSpriteAddr { x },
+            (0xF, _, 0x3, 0x3) => Instruction::StoreBcd { x },
+            (0xF, _, 0x3, 0xA) => Instruction::SetPitch { x },
+            (0xF, _, 0x5, 0x5) => Instruction::StoreRegisters { x },
+            (0xF, _, 0x6, 0x5) => Instruction::LoadRegisters { x },
+            (0xF, _, 0x7, 0x5) => Instruction::SaveFlags { x },
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags { x },
+            _ => return Err(DecodeError { opcode: word }),
+        };
+
+        Ok(instruction)
+    }
+
+    // Decodes as CHIP-8X does, where 02A0, BXYN, EXF2 and EXF5 are colour and second
+    // keypad instructions. BNNN is not available.
+    pub fn decode_chip8x(word: u16) -> Result<Self, DecodeError> {
+        let c = (word & 0xF000) >> 12;
+        let x = ((word & 0x0F00) >> 8) as u8;
+        let y = ((word & 0x00F0) >> 4) as u8;
+        let n = (word & 0x000F) as u8;
+
+        match (c, x, y, n) {
+            (0x0, 0x2, 0xA, 0x0) => Ok(Instruction::CycleBackground),
+            (0xB, _, _, 0x0) => Ok(Instruction::SetZoneColour { x, y }),
+            (0xB, _, _, _) => Ok(Instruction::SetRowColour { x, y, n }),
+            (0xE, _, 0xF, 0x2) => Ok(Instruction::SkipIfKey2 { x }),
+            (0xE, _, 0xF, 0x5) => Ok(Instruction::SkipIfNotKey2 { x }),
+            _ => Self::decode(word),
+        }
+    }
+
+    pub fn encode(&self) -> u16 {
+        match *self {
+            Instruction::Halt => 0x0000,
+            Instruction::CallRoutine { nnn } => address(0x0, nnn),
+            Instruction::CycleBackground => 0x02A0,
+            Instruction::ScrollDown { n } => 0x00C0 | (n & 0xF) as u16,
+            Instruction::ScrollUp { n } => 0x00D0 | (n & 0xF) as u16,
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Goto { nnn } => address(0x1, nnn),
+            Instruction::Call { nnn } => address(0x2, nnn),
+            Instruction::SkipXEqNN { x, nn } => constant(0x3, x, nn),
+            Instruction::SkipXNeqNN { x, nn } => constant(0x4, x, nn),
+            Instruction::SkipXEqY { x, y } => registers(0x5, x, y, 0x0),
+            Instruction::StoreRange { x, y } => registers(0x5, x, y, 0x2),
+            Instruction::LoadRange { x, y } => registers(0x5, x, y, 0x3),
+            Instruction::SetXToNN { x, nn } => constant(0x6, x, nn),
+            Instruction::AddNNToX { x, nn } => constant(0x7, x, nn),
+            Instruction::SetXToY { x, y } => registers(0x8, x, y, 0x0),
+            Instruction::Or { x, y } => registers(0x8, x, y, 0x1),
+            Instruction::And { x, y } => registers(0x8, x, y, 0x2),
+            Instruction::Xor { x, y } => registers(0x8, x, y, 0x3),
+            Instruction::AddXY { x, y } => registers(0x8, x, y, 0x4),
+            Instruction::SubXY { x, y } => registers(0x8, x, y, 0x5),
+            Instruction::ShiftRight { x, y } => registers(0x8, x, y, 0x6),
+            Instruction::SubYX { x, y } => registers(0x8, x, y, 0x7),
+            Instruction::ShiftLeft { x, y } => registers(0x8, x, y, 0xE),
+            Instruction::SkipXNeqY { x, y } => registers(0x9, x, y, 0x0),
+            Instruction::SetIToNNN { nnn } => address(0xA, nnn),
+            Instruction::JumpToNNNPlusV0 { nnn } => address(0xB, nnn),
+            Instruction::SetZoneColour { x, y } => registers(0xB, x, y, 0x0),
+            Instruction::SetRowColour { x, y, n } => registers(0xB, x, y, n),
+            Instruction::Rand { x, nn } => constant(0xC, x, nn),
+            Instruction::Draw { x, y, n } => registers(0xD, x, y, n),
+            Instruction::SkipIfKey { x } => constant(0xE, x, 0x9E),
+            Instruction::SkipIfNotKey { x } => constant(0xE, x, 0xA1),
+            Instruction::SkipIfKey2 { x } => constant(0xE, x, 0xF2),
+            Instruction::SkipIfNotKey2 { x } => constant(0xE, x, 0xF5),
+            Instruction::SetIToLong => 0xF000,
+            Instruction::SelectPlanes { n } => constant(0xF, n, 0x01),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::SetXToTimer { x } => constant(0xF, x, 0x07),
+            Instruction::AwaitKey { x } => constant(0xF, x, 0x0A),
+            Instruction::SetTimerToX { x } => constant(0xF, x, 0x15),
+            Instruction::SetSoundTimer { x } => constant(0xF, x, 0x18),
+            Instruction::AddXToI { x } => constant(0xF, x, 0x1E),
+            Instruction::SetIToSpriteAddr { x } => constant(0xF, x, 0x29),
+            Instruction::SetIToLargeSpriteAddr { x } => constant(0xF, x, 0x30),
+            Instruction::StoreBcd { x } => constant(0xF, x, 0x33),
+            Instruction::SetPitch { x } => constant(0xF, x, 0x3A),
+            Instruction::StoreRegisters { x } => constant(0xF, x, 0x55),
+            Instruction::LoadRegisters { x } => constant(0xF, x, 0x65),
+            Instruction::SaveFlags { x } => constant(0xF, x, 0x75),
+            Instruction::LoadFlags { x } => constant(0xF, x, 0x85),
+        }
+    }
+}
+
+fn address(c: u16, nnn: Address) -> u16 {
+    c << 12 | u16::from(nnn) & 0x0FFF
+}
+
+fn constant(c: u16, x: u8, nn: u8) -> u16 {
+    c << 12 | ((x & 0xF) as u16) << 8 | nn as u16
+}
+
+fn registers(c: u16, x: u8, y: u8, n: u8) -> u16 {
+    c << 12 | ((x & 0xF) as u16) << 8 | ((y & 0xF) as u16) << 4 | (n & 0xF) as u16
+}
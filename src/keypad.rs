@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+pub const KEY_COUNT: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyEvent {
+    Press(u8),
+    Release(u8),
+}
+
+// When FX0A hands back a key. The COSMAC VIP interpreter only stores the key once it is
+// let go again, many later interpreters return as soon as a key is down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AwaitMode {
+    Press,
+    Release,
+}
+
+// State of the 16 key hex keypad, keys 0-F.
+// Hosts call press/release directly, or schedule events against frame numbers so a run
+// can be replayed exactly; scheduled events are applied at the start of each frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keypad {
+    pub await_mode: AwaitMode,
+    keys: [bool; KEY_COUNT],
+    awaiting: Option<u8>,
+    script: VecDeque<(u64, KeyEvent)>,
+}
+
+impl Keypad {
+    pub fn new(await_mode: AwaitMode) -> Self {
+        Self {
+            await_mode,
+            keys: [false; KEY_COUNT],
+            awaiting: None,
+            script: VecDeque::new(),
+        }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.keys[(key & 0xF) as usize] = true;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.keys[(key & 0xF) as usize] = false;
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys = [false; KEY_COUNT];
+    }
+
+    // Releases every key and abandons any FX0A wait in progress. The await mode and the
+    // scheduled events are kept.
+    pub fn reset(&mut self) {
+        self.release_all();
+        self.awaiting = None;
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0xF) as usize]
+    }
+
+    pub fn apply(&mut self, event: KeyEvent) {
+        match event {
+            KeyEvent::Press(key) => self.press(key),
+            KeyEvent::Release(key) => self.release(key),
+        }
+    }
+
+    // Queues an event to happen at the start of the given frame. Events may be scheduled in
+    // any order; those due on the same frame happen in the order they were scheduled.
+    pub fn schedule(&mut self, frame: u64, event: KeyEvent) {
+        let position = self.script.partition_point(|(due, _)| *due <= frame);
+        self.script.insert(position, (frame, event));
+    }
+
+    // Schedules a press at `frame` and the matching release `held` frames later
+    pub fn tap(&mut self, frame: u64, key: u8, held: u64) {
+        self.schedule(frame, KeyEvent::Press(key));
+        self.schedule(frame + held, KeyEvent::Release(key));
+    }
+
+    pub fn has_scheduled(&self) -> bool {
+        !self.script.is_empty()
+    }
+
+    // Applies every scheduled event that is due by `frame`
+    pub fn update(&mut self, frame: u64) {
+        while let Some((due, event)) = self.script.front().copied() {
+            if due > frame {
+                break;
+            }
+
+            self.apply(event);
+            self.script.pop_front();
+        }
+    }
+
+    // Polled by FX0A each time it executes, returns the key once the wait is satisfied
+    pub fn awaited_key(&mut self) -> Option<u8> {
+        let held = self.keys.iter().position(|pressed| *pressed).map(|key| key as u8);
+
+        match self.await_mode {
+            AwaitMode::Press => held,
+            AwaitMode::Release => match self.awaiting {
+                Some(key) if !self.is_pressed(key) => self.awaiting.take(),
+                Some(_) => None,
+                None => {
+                    self.awaiting = held;
+                    None
+                }
+            },
+        }
+    }
+}
+
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new(AwaitMode::Press)
+    }
+}
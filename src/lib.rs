@@ -0,0 +1,29 @@
+#[macro_use]
+mod macros;
+pub mod floating_point;
+pub mod fixed_point;
+pub mod rand;
+pub mod processor;
+pub mod quirks;
+pub mod platform;
+pub mod cdp1802;
+pub mod opcodes;
+pub mod address;
+pub mod error;
+pub mod instruction;
+pub mod disasm;
+pub mod expr;
+pub mod asm;
+pub mod octo;
+pub mod terminal;
+pub mod screenshot;
+pub mod recording;
+pub mod audio;
+pub mod cli;
+pub mod timers;
+pub mod display;
+pub mod keypad;
+pub mod font;
+
+#[cfg(test)]
+mod tests;
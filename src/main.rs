@@ -1,14 +1,19 @@
-#[macro_use]
-mod macros;
-mod floating_point;
-mod fixed_point;
-mod rand;
-mod processor;
-mod opcodes;
-mod address;
-
-#[cfg(test)]
-mod tests;
+use std::io;
+use std::process;
+use cpu_emulator::cli;
+use cpu_emulator::error::CliError;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let stdout = io::stdout();
+
+    if let Err(err) = cli::run(&args, &mut stdout.lock()) {
+        eprintln!("cpu_emulator: {}", err);
+
+        if let CliError::Usage(_) = err {
+            eprintln!("\n{}", cli::USAGE);
+            process::exit(2);
+        }
+        process::exit(1);
+    }
 }
@@ -0,0 +1,901 @@
+// Compiler for Octo, the structured CHIP-8 assembly language.
+//
+//     :const SPEED 3
+//     :alias x v1
+//     : main
+//         x := 0
+//         loop
+//             x += SPEED
+//             if x >= 30 then jump done
+//         again
+//     : done
+//         if x == 30 begin v2 := 1 else v2 := 2 end
+//
+// Besides the statements above this covers `:calc`, `:macro`, `:org`, `:byte`, `:call`,
+// `while` inside loops and the `<`, `>`, `<=`, `>=` comparisons, which use vF as scratch
+// like Octo itself. Everything lowers to the same `Instruction`s the `OpCode` builders use.
+// Octo programs start at `main`; unless it comes before any other label or code a jump to
+// it is placed at the origin.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use super::address::Address;
+use super::asm::Rom;
+use super::disasm::DEFAULT_ORIGIN;
+use super::error::AsmError;
+use super::expr;
+use super::instruction::Instruction;
+
+const FLAG: u8 = 0xF;
+const MAX_EXPANSIONS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Register(u8),
+    Constant(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Condition {
+    Compare(u8, Comparison, Operand),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    fn negate(self) -> Self {
+        match self {
+            Condition::Compare(x, comparison, rhs) => {
+                let negated = match comparison {
+                    Comparison::Equal => Comparison::NotEqual,
+                    Comparison::NotEqual => Comparison::Equal,
+                    Comparison::Less => Comparison::GreaterEqual,
+                    Comparison::GreaterEqual => Comparison::Less,
+                    Comparison::Greater => Comparison::LessEqual,
+                    Comparison::LessEqual => Comparison::Greater,
+                };
+                Condition::Compare(x, negated, rhs)
+            }
+            Condition::Key(x) => Condition::NotKey(x),
+            Condition::NotKey(x) => Condition::Key(x),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Loop {
+    start: u16,
+    exits: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+struct Branch {
+    pending: u16,
+    has_else: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Fixup {
+    address: u16,
+    name: String,
+    line: usize,
+    // the 16 bit word after `i := long` rather than an NNN operand
+    long: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compiler {
+    pub origin: u16,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self { origin: DEFAULT_ORIGIN }
+    }
+}
+
+impl Compiler {
+    pub fn new(origin: u16) -> Self {
+        Self { origin }
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Rom, AsmError> {
+        self.compile_source(source, "<source>")
+    }
+
+    pub fn compile_file<P: AsRef<Path>>(&self, path: P) -> Result<Rom, AsmError> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let source = fs::read_to_string(path).map_err(|err| AsmError {
+            file: name.clone(),
+            line: 0,
+            message: format!("cannot read {}: {}", name, err),
+        })?;
+
+        self.compile_source(&source, &name)
+    }
+
+    fn compile_source(&self, source: &str, name: &str) -> Result<Rom, AsmError> {
+        let mut state = State::new(self.origin, name, tokenize(source));
+
+        state.program()?;
+
+        Ok(Rom {
+            origin: self.origin,
+            bytes: state.rom,
+            symbols: state.labels.iter().map(|(name, address)| (name.clone(), *address as i64))
+                .chain(state.constants.iter().map(|(name, value)| (name.clone(), *value)))
+                .collect(),
+        })
+    }
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+
+        for word in code.split_whitespace() {
+            tokens.push_back(Token { text: word.to_string(), line: index + 1 });
+        }
+    }
+
+    tokens
+}
+
+struct State {
+    file: String,
+    origin: u16,
+    pc: u16,
+    rom: Vec<u8>,
+    tokens: VecDeque<Token>,
+    line: usize,
+    entered: bool,
+    expansions: usize,
+    labels: BTreeMap<String, u16>,
+    constants: BTreeMap<String, i64>,
+    aliases: BTreeMap<String, u8>,
+    macros: BTreeMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    loops: Vec<Loop>,
+    branches: Vec<Branch>,
+}
+
+impl State {
+    fn new(origin: u16, file: &str, tokens: VecDeque<Token>) -> Self {
+        Self {
+            file: file.to_string(),
+            origin,
+            pc: origin,
+            rom: Vec::new(),
+            tokens,
+            line: 0,
+            entered: false,
+            expansions: 0,
+            labels: BTreeMap::new(),
+            constants: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            macros: BTreeMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+        }
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> AsmError {
+        AsmError { file: self.file.clone(), line: self.line, message: message.into() }
+    }
+
+    fn program(&mut self) -> Result<(), AsmError> {
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if let Some(open) = self.loops.last() {
+            return Err(self.error(format!("loop at {:#X} has no matching again", open.start)));
+        }
+        if !self.branches.is_empty() {
+            return Err(self.error("if ... begin has no matching end"));
+        }
+
+        if !self.labels.contains_key("main") {
+            return Err(self.error("program has no main"));
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+
+            match self.labels.get(&fixup.name) {
+                Some(target) if fixup.long => {
+                    let offset = (fixup.address - self.origin) as usize;
+                    self.rom[offset..offset + 2].copy_from_slice(&target.to_be_bytes());
+                }
+                Some(target) => {
+                    let target = *target;
+                    self.patch(fixup.address, target)?
+                }
+                None => return Err(self.error(format!("undefined name '{}'", fixup.name))),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<String, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            }
+            None => Err(self.error("unexpected end of program")),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        let found = self.next()?;
+
+        if found == expected {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}', found '{}'", expected, found)))
+        }
+    }
+
+    // Places the jump to main the first time anything else takes up space
+    fn enter(&mut self) -> Result<(), AsmError> {
+        if !self.entered {
+            self.entered = true;
+            self.jump_to_label(|nnn| Instruction::Goto { nnn }, "main")?;
+        }
+
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        self.enter()?;
+
+        if self.pc < self.origin {
+            return Err(self.error(format!("address {:#X} is below the origin", self.pc)));
+        }
+
+        let offset = (self.pc - self.origin) as usize;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+
+        self.rom[offset] = byte;
+        self.pc = self.pc.checked_add(1).ok_or_else(|| self.error("program is too large"))?;
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<u16, AsmError> {
+        self.enter()?;
+        let address = self.pc;
+        let word = instruction.encode();
+
+        self.emit_byte((word >> 8) as u8)?;
+        self.emit_byte(word as u8)?;
+        Ok(address)
+    }
+
+    // Rewrites the 12 bit address field of the instruction at `address`
+    fn patch(&mut self, address: u16, target: u16) -> Result<(), AsmError> {
+        if target > 0xFFF {
+            return Err(self.error(format!("{:#X} is not a 12 bit address", target)));
+        }
+
+        let offset = (address - self.origin) as usize;
+        self.rom[offset] = (self.rom[offset] & 0xF0) | (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+        Ok(())
+    }
+
+    // Emits an instruction taking an address, deferring it if the label is not known yet
+    fn jump_to_label<F>(&mut self, build: F, name: &str) -> Result<(), AsmError>
+    where
+        F: Fn(Address) -> Instruction,
+    {
+        match self.labels.get(name) {
+            Some(target) if *target <= 0xFFF => {
+                let nnn = Address::from(*target);
+                self.emit(build(nnn))?;
+            }
+            Some(target) => return Err(self.error(format!("{:#X} is not a 12 bit address", target))),
+            None => {
+                let address = self.emit(build(Address::from(0_u16)))?;
+                self.fixups.push(Fixup { address, name: name.to_string(), line: self.line, long: false });
+            }
+        }
+
+        Ok(())
+    }
+
+    // An address operand: a number, constant or label (possibly defined later)
+    fn address_operand<F>(&mut self, build: F) -> Result<(), AsmError>
+    where
+        F: Fn(Address) -> Instruction,
+    {
+        let token = self.next()?;
+
+        if let Some(value) = self.known_value(&token) {
+            if !(0..=0xFFF).contains(&value) {
+                return Err(self.error(format!("{:#X} is not a 12 bit address", value)));
+            }
+            self.emit(build(Address::from(value as u16)))?;
+            return Ok(());
+        }
+
+        if expr::is_identifier(&token) || is_name(&token) {
+            return self.jump_to_label(build, &token);
+        }
+
+        Err(self.error(format!("'{}' is not an address", token)))
+    }
+
+    fn known_value(&self, token: &str) -> Option<i64> {
+        expr::parse_number(token)
+            .or_else(|| token.strip_prefix('-').and_then(expr::parse_number).map(|n| -n))
+            .or_else(|| self.constants.get(token).copied())
+            .or_else(|| self.labels.get(token).map(|address| *address as i64))
+    }
+
+    fn value(&mut self) -> Result<i64, AsmError> {
+        let token = self.next()?;
+
+        self.known_value(&token).ok_or_else(|| self.error(format!("'{}' is not a known value", token)))
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let value = self.value()?;
+
+        if (-0x80..=0xFF).contains(&value) {
+            Ok(value as u8)
+        } else {
+            Err(self.error(format!("{} does not fit in a byte", value)))
+        }
+    }
+
+    fn register_of(&self, token: &str) -> Option<u8> {
+        if let Some(register) = self.aliases.get(token) {
+            return Some(*register);
+        }
+
+        let lower = token.to_ascii_lowercase();
+        match lower.strip_prefix('v') {
+            Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+
+        self.register_of(&token).ok_or_else(|| self.error(format!("'{}' is not a register", token)))
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        match self.peek().and_then(|token| self.register_of(token)) {
+            Some(register) => {
+                self.next()?;
+                Ok(Operand::Register(register))
+            }
+            None => Ok(Operand::Constant(self.byte()?)),
+        }
+    }
+
+    fn define_name(&mut self) -> Result<String, AsmError> {
+        let name = self.next()?;
+
+        if !is_name(&name) || self.register_of(&name).is_some() {
+            return Err(self.error(format!("'{}' cannot be used as a name", name)));
+        }
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) || self.macros.contains_key(&name) {
+            return Err(self.error(format!("'{}' is already defined", name)));
+        }
+
+        Ok(name)
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+
+        match token.as_str() {
+            ":" => {
+                let name = self.define_name()?;
+                if name == "main" {
+                    self.entered = true;
+                }
+                self.enter()?;
+                self.labels.insert(name, self.pc);
+            }
+            ":const" => {
+                let name = self.define_name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":calc" => {
+                let name = self.define_name()?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let address = self.value()?;
+                if !(0..=0xFFFF).contains(&address) {
+                    return Err(self.error(format!("{:#X} is not a valid address", address)));
+                }
+                self.enter()?;
+                self.pc = address as u16;
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") { self.calc()? } else { self.value()? };
+                if !(-0x80..=0xFF).contains(&value) {
+                    return Err(self.error(format!("{} does not fit in a byte", value)));
+                }
+                self.emit_byte(value as u8)?;
+            }
+            ":call" => self.address_operand(|nnn| Instruction::Call { nnn })?,
+            ":breakpoint" => {
+                self.next()?;
+            }
+            "clear" => {
+                self.emit(Instruction::Clear)?;
+            }
+            "return" | ";" => {
+                self.emit(Instruction::Return)?;
+            }
+            "jump" => self.address_operand(|nnn| Instruction::Goto { nnn })?,
+            "jump0" => self.address_operand(|nnn| Instruction::JumpToNNNPlusV0 { nnn })?,
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.value()?;
+                if !(0..=0xF).contains(&n) {
+                    return Err(self.error(format!("{} does not fit in a nibble", n)));
+                }
+                self.emit(Instruction::Draw { x, y, n: n as u8 })?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreBcd { x })?;
+            }
+            "save" => self.register_range(|x| Instruction::StoreRegisters { x }, |x, y| Instruction::StoreRange { x, y })?,
+            "load" => self.register_range(|x| Instruction::LoadRegisters { x }, |x, y| Instruction::LoadRange { x, y })?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let instruction = match token.as_str() {
+                    "delay" => Instruction::SetTimerToX { x },
+                    "buzzer" => Instruction::SetSoundTimer { x },
+                    _ => Instruction::SetPitch { x },
+                };
+                self.emit(instruction)?;
+            }
+            "audio" => {
+                self.emit(Instruction::LoadAudioPattern)?;
+            }
+            "scroll-down" => {
+                let n = self.value()?;
+                if !(0..=0xF).contains(&n) {
+                    return Err(self.error(format!("{} does not fit in a nibble", n)));
+                }
+                self.emit(Instruction::ScrollDown { n: n as u8 })?;
+            }
+            "scroll-up" => {
+                let n = self.value()?;
+                if !(0..=0xF).contains(&n) {
+                    return Err(self.error(format!("{} does not fit in a nibble", n)));
+                }
+                self.emit(Instruction::ScrollUp { n: n as u8 })?;
+            }
+            "plane" => {
+                let n = self.value()?;
+                if !(0..=0x3).contains(&n) {
+                    return Err(self.error(format!("plane {} is not 0 to 3", n)));
+                }
+                self.emit(Instruction::SelectPlanes { n: n as u8 })?;
+            }
+            "scroll-right" => {
+                self.emit(Instruction::ScrollRight)?;
+            }
+            "scroll-left" => {
+                self.emit(Instruction::ScrollLeft)?;
+            }
+            "exit" => {
+                self.emit(Instruction::Exit)?;
+            }
+            "lores" => {
+                self.emit(Instruction::LowRes)?;
+            }
+            "hires" => {
+                self.emit(Instruction::HighRes)?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::SaveFlags { x })?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags { x })?;
+            }
+            "i" => self.index_statement()?,
+            "if" => self.if_statement()?,
+            "else" => {
+                let branch = self.branches.last().cloned().ok_or_else(|| self.error("else without if ... begin"))?;
+                if branch.has_else {
+                    return Err(self.error("if ... begin has two else branches"));
+                }
+
+                let skip_else = self.emit(Instruction::Goto { nnn: Address::from(0_u16) })?;
+                let target = self.pc;
+                self.patch(branch.pending, target)?;
+
+                let open = self.branches.last_mut().unwrap();
+                open.pending = skip_else;
+                open.has_else = true;
+            }
+            "end" => {
+                let branch = self.branches.pop().ok_or_else(|| self.error("end without if ... begin"))?;
+                let target = self.pc;
+                self.patch(branch.pending, target)?;
+            }
+            "loop" => {
+                self.enter()?;
+                self.loops.push(Loop { start: self.pc, exits: Vec::new() });
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error("while outside of a loop"));
+                }
+
+                let condition = self.condition()?;
+                self.skip_unless(condition.negate())?;
+                let exit = self.emit(Instruction::Goto { nnn: Address::from(0_u16) })?;
+                self.loops.last_mut().unwrap().exits.push(exit);
+            }
+            "again" => {
+                let open = self.loops.pop().ok_or_else(|| self.error("again without loop"))?;
+                self.emit(Instruction::Goto { nnn: Address::from(open.start) })?;
+
+                let target = self.pc;
+                for exit in open.exits {
+                    self.patch(exit, target)?;
+                }
+            }
+            _ => {
+                if let Some(x) = self.register_of(&token) {
+                    return self.register_statement(x);
+                }
+
+                if let Some(body) = self.macros.get(&token).cloned() {
+                    return self.expand(&token, body);
+                }
+
+                if let Some(value) = self.known_value(&token).filter(|_| !self.labels.contains_key(&token)) {
+                    if !(-0x80..=0xFF).contains(&value) {
+                        return Err(self.error(format!("{} does not fit in a byte", value)));
+                    }
+                    return self.emit_byte(value as u8);
+                }
+
+                // a bare name calls the subroutine of that name
+                if is_name(&token) {
+                    return self.jump_to_label(|nnn| Instruction::Call { nnn }, &token);
+                }
+
+                return Err(self.error(format!("unexpected '{}'", token)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn index_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+
+        match op.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(Instruction::SetIToSpriteAddr { x })?;
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(Instruction::SetIToLargeSpriteAddr { x })?;
+                }
+                Some("long") => {
+                    self.next()?;
+                    self.long_address()?;
+                }
+                _ => self.address_operand(|nnn| Instruction::SetIToNNN { nnn })?,
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instruction::AddXToI { x })?;
+            }
+            _ => return Err(self.error(format!("unknown operation 'i {}'", op))),
+        }
+
+        Ok(())
+    }
+
+    // `i := long` and its 16 bit address, which may be a label defined later
+    fn long_address(&mut self) -> Result<(), AsmError> {
+        self.emit(Instruction::SetIToLong)?;
+        let token = self.next()?;
+
+        let address = match self.known_value(&token) {
+            Some(value) if (0..=0xFFFF).contains(&value) => value as u16,
+            Some(value) => return Err(self.error(format!("{:#X} is not a 16 bit address", value))),
+            None if expr::is_identifier(&token) || is_name(&token) => {
+                self.fixups.push(Fixup { address: self.pc, name: token, line: self.line, long: true });
+                0
+            }
+            None => return Err(self.error(format!("'{}' is not an address", token))),
+        };
+
+        self.emit_byte((address >> 8) as u8)?;
+        self.emit_byte(address as u8)
+    }
+
+    // `save vX` or `load vX`, or the XO-CHIP range forms `save vX - vY` and `load vX - vY`
+    fn register_range<F, G>(&mut self, single: F, range: G) -> Result<(), AsmError>
+    where
+        F: Fn(u8) -> Instruction,
+        G: Fn(u8, u8) -> Instruction,
+    {
+        let x = self.register()?;
+
+        let instruction = if self.peek() == Some("-") {
+            self.next()?;
+            range(x, self.register()?)
+        } else {
+            single(x)
+        };
+
+        self.emit(instruction)?;
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), AsmError> {
+        let op = self.next()?;
+
+        let instruction = match op.as_str() {
+            ":=" => match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    Instruction::Rand { x, nn: self.byte()? }
+                }
+                Some("delay") => {
+                    self.next()?;
+                    Instruction::SetXToTimer { x }
+                }
+                Some("key") => {
+                    self.next()?;
+                    Instruction::AwaitKey { x }
+                }
+                _ => match self.operand()? {
+                    Operand::Register(y) => Instruction::SetXToY { x, y },
+                    Operand::Constant(nn) => Instruction::SetXToNN { x, nn },
+                },
+            },
+            "+=" => match self.operand()? {
+                Operand::Register(y) => Instruction::AddXY { x, y },
+                Operand::Constant(nn) => Instruction::AddNNToX { x, nn },
+            },
+            "-=" => match self.operand()? {
+                Operand::Register(y) => Instruction::SubXY { x, y },
+                Operand::Constant(nn) => Instruction::AddNNToX { x, nn: nn.wrapping_neg() },
+            },
+            "=-" => Instruction::SubYX { x, y: self.register()? },
+            "|=" => Instruction::Or { x, y: self.register()? },
+            "&=" => Instruction::And { x, y: self.register()? },
+            "^=" => Instruction::Xor { x, y: self.register()? },
+            ">>=" => Instruction::ShiftRight { x, y: self.register()? },
+            "<<=" => Instruction::ShiftLeft { x, y: self.register()? },
+            _ => return Err(self.error(format!("unknown operation '{}'", op))),
+        };
+
+        self.emit(instruction)?;
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()?;
+        let op = self.next()?;
+
+        let comparison = match op.as_str() {
+            "key" => return Ok(Condition::Key(x)),
+            "-key" => return Ok(Condition::NotKey(x)),
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessEqual,
+            ">=" => Comparison::GreaterEqual,
+            _ => return Err(self.error(format!("unknown comparison '{}'", op))),
+        };
+
+        Ok(Condition::Compare(x, comparison, self.operand()?))
+    }
+
+    // Emits code after which the next instruction only runs when `condition` holds
+    fn skip_unless(&mut self, condition: Condition) -> Result<(), AsmError> {
+        use Comparison::*;
+        use Operand::*;
+
+        let instructions = match condition {
+            Condition::Key(x) => vec![Instruction::SkipIfNotKey { x }],
+            Condition::NotKey(x) => vec![Instruction::SkipIfKey { x }],
+            Condition::Compare(x, Equal, Constant(nn)) => vec![Instruction::SkipXNeqNN { x, nn }],
+            Condition::Compare(x, Equal, Register(y)) => vec![Instruction::SkipXNeqY { x, y }],
+            Condition::Compare(x, NotEqual, Constant(nn)) => vec![Instruction::SkipXEqNN { x, nn }],
+            Condition::Compare(x, NotEqual, Register(y)) => vec![Instruction::SkipXEqY { x, y }],
+            // The rest load the right hand side into vF and subtract, so the no-borrow flag
+            // left in vF decides: `vF =- vx` flags vx >= rhs, `vF -= vx` flags rhs >= vx
+            Condition::Compare(x, comparison, rhs) => {
+                let load = match rhs {
+                    Register(y) => Instruction::SetXToY { x: FLAG, y },
+                    Constant(nn) => Instruction::SetXToNN { x: FLAG, nn },
+                };
+
+                let (subtract, flag) = match comparison {
+                    GreaterEqual => (Instruction::SubYX { x: FLAG, y: x }, 1),
+                    Less => (Instruction::SubYX { x: FLAG, y: x }, 0),
+                    LessEqual => (Instruction::SubXY { x: FLAG, y: x }, 1),
+                    Greater => (Instruction::SubXY { x: FLAG, y: x }, 0),
+                    Equal | NotEqual => unreachable!(),
+                };
+
+                vec![load, subtract, Instruction::SkipXNeqNN { x: FLAG, nn: flag }]
+            }
+        };
+
+        for instruction in instructions {
+            self.emit(instruction)?;
+        }
+
+        Ok(())
+    }
+
+    fn if_statement(&mut self) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+
+        match self.next()?.as_str() {
+            "then" => self.skip_unless(condition),
+            "begin" => {
+                self.skip_unless(condition.negate())?;
+                let pending = self.emit(Instruction::Goto { nnn: Address::from(0_u16) })?;
+                self.branches.push(Branch { pending, has_else: false });
+                Ok(())
+            }
+            other => Err(self.error(format!("expected 'then' or 'begin', found '{}'", other))),
+        }
+    }
+
+    // Reads `{ tokens }` and evaluates them as an expression
+    fn calc(&mut self) -> Result<i64, AsmError> {
+        self.expect("{")?;
+
+        let mut source = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "}" {
+                break;
+            }
+            source.push(token);
+        }
+
+        let source = source.join(" ");
+        let constants = &self.constants;
+        let labels = &self.labels;
+
+        expr::evaluate(&source, |name| {
+            constants.get(name).copied()
+                .or_else(|| labels.get(name).map(|address| *address as i64))
+                .ok_or_else(|| format!("'{}' is not a known value", name))
+        })
+        .map_err(|message| self.error(message))
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.define_name()?;
+        let mut params = Vec::new();
+
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.tokens.pop_front().ok_or_else(|| self.error(format!("macro '{}' is never closed", name)))?;
+
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    // Substitutes the arguments into the macro body and queues it to be compiled next
+    fn expand(&mut self, name: &str, body: Macro) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("macro '{}' expands without end", name)));
+        }
+
+        let mut arguments = BTreeMap::new();
+        for param in &body.params {
+            let argument = self.next().map_err(|_| self.error(format!("macro '{}' needs {} arguments", name, body.params.len())))?;
+            arguments.insert(param.clone(), argument);
+        }
+
+        let line = self.line;
+        for token in body.body.iter().rev() {
+            let text = arguments.get(&token.text).cloned().unwrap_or_else(|| token.text.clone());
+            self.tokens.push_front(Token { text, line });
+        }
+
+        Ok(())
+    }
+}
+
+// Octo names can contain most printable characters, but not start like a number
+fn is_name(token: &str) -> bool {
+    let reserved = [":", ":=", "{", "}", ";", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<="];
+
+    !token.is_empty()
+        && !reserved.contains(&token)
+        && !token.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && token.chars().all(|c| c.is_ascii_graphic())
+}
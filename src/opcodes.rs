@@ -1,3 +1,7 @@
+use super::address::Address;
+use super::error::DecodeError;
+use super::instruction::Instruction;
+
 #[derive(Debug, Clone, Copy)]
 pub struct OpCode(u8, u8);
 
@@ -13,6 +17,17 @@ impl OpCode {
     pub fn low_byte(&self) -> u8 {
         self.1
     }
+
+    pub fn decode(&self) -> Result<Instruction, DecodeError> {
+        Instruction::decode(self.into())
+    }
+}
+
+impl From<Instruction> for OpCode {
+    fn from(instruction: Instruction) -> Self {
+        let word = instruction.encode();
+        Self ((word >> BYTE) as u8, word as u8)
+    }
 }
 
 impl From<OpCode> for u16 {
@@ -23,7 +38,7 @@ impl From<OpCode> for u16 {
 
 impl From<&OpCode> for u16 {
     fn from(c: &OpCode) -> u16 {
-        c.into()
+        (*c).into()
     }
 }
 
@@ -31,171 +46,249 @@ impl OpCode {
 
     // Halt execution
     pub fn halt() -> Self {
-        Self (0x00, 0x00)
+        Instruction::Halt.into()
     }
 
     // Calls machine code routine (RCA 1802 for COSMAC VIP) at address NNN. Not necessary for most ROMs
     pub fn call_r(n1: u8, n2: u8, n3: u8) -> Self {
-        Self (0x0 << NIBBLE | n1, n2 << NIBBLE | n3)
+        Instruction::CallRoutine { nnn: Address (n1, n2, n3) }.into()
+    }
+
+    // Steps the background through blue, black, green and red (CHIP-8X)
+    pub fn cycle_background() -> Self {
+        Instruction::CycleBackground.into()
     }
 
     // Clears the screen
     pub fn clear() -> Self {
-        Self (0x00, 0xE0)
+        Instruction::Clear.into()
     }
 
     // Returns from a subroutine.
     pub fn ret() -> Self {
-        Self (0x00, 0xEE)
+        Instruction::Return.into()
+    }
+
+    // Scrolls the display down by N pixels (SUPER-CHIP)
+    pub fn scroll_down(n: u8) -> Self {
+        Instruction::ScrollDown { n }.into()
+    }
+
+    // Scrolls the display up by N pixels (XO-CHIP)
+    pub fn scroll_up(n: u8) -> Self {
+        Instruction::ScrollUp { n }.into()
+    }
+
+    // Scrolls the display right by 4 pixels (SUPER-CHIP)
+    pub fn scroll_right() -> Self {
+        Instruction::ScrollRight.into()
+    }
+
+    // Scrolls the display left by 4 pixels (SUPER-CHIP)
+    pub fn scroll_left() -> Self {
+        Instruction::ScrollLeft.into()
+    }
+
+    // Exits the interpreter (SUPER-CHIP)
+    pub fn exit() -> Self {
+        Instruction::Exit.into()
+    }
+
+    // Switches to the 64x32 low resolution display (SUPER-CHIP)
+    pub fn low_res() -> Self {
+        Instruction::LowRes.into()
+    }
+
+    // Switches to the 128x64 high resolution display (SUPER-CHIP)
+    pub fn high_res() -> Self {
+        Instruction::HighRes.into()
     }
 
     // Jumps to address NNN
     pub fn goto(n1: u8, n2: u8, n3: u8) -> Self {
-        Self (0x1 << NIBBLE | n1, n2 << NIBBLE | n3)
+        Instruction::Goto { nnn: Address (n1, n2, n3) }.into()
     }
 
     // Calls subroutine at NNN
     pub fn call(n1: u8, n2: u8, n3: u8) -> Self {
-        Self (0x2 << NIBBLE | n1, n2 << NIBBLE | n3)
+        Instruction::Call { nnn: Address (n1, n2, n3) }.into()
     }
 
     // Skips the next instruction if VX equals NN
     pub fn skip_x_eq_nn(x: u8, n2: u8, n3: u8) -> Self {
-        Self (0x3 << NIBBLE | x, n2 << NIBBLE | n3)
+        Instruction::SkipXEqNN { x, nn: n2 << NIBBLE | n3 }.into()
     }
 
     // 	Skips the next instruction if VX does not equal NN
     pub fn skip_x_neq_nn(x: u8, n2: u8, n3: u8) -> Self {
-        Self (0x4 << NIBBLE | x, n2 << NIBBLE | n3)
+        Instruction::SkipXNeqNN { x, nn: n2 << NIBBLE | n3 }.into()
     }
 
     // Skips the next instruction if VX equals VY
     pub fn skip_x_eq_y(x: u8, y: u8) -> Self {
-        Self (0x5 << NIBBLE | x, y << NIBBLE | 0x0)
+        Instruction::SkipXEqY { x, y }.into()
+    }
+
+    // Stores VX to VY in memory starting at I, in reverse order when X is greater than Y.
+    // I is left unmodified (XO-CHIP)
+    pub fn store_x_to_y_to_mem(x: u8, y: u8) -> Self {
+        Instruction::StoreRange { x, y }.into()
+    }
+
+    // Fills VX to VY from memory starting at I, in reverse order when X is greater than Y.
+    // I is left unmodified (XO-CHIP)
+    pub fn fill_x_to_y_from_mem(x: u8, y: u8) -> Self {
+        Instruction::LoadRange { x, y }.into()
     }
 
     // Sets VX to NN
     pub fn set_x_to_nn(x: u8, n2: u8, n3: u8) -> Self {
-        Self (0x6 << NIBBLE | x, n2 << NIBBLE | n3)
+        Instruction::SetXToNN { x, nn: n2 << NIBBLE | n3 }.into()
     }
 
     // Adds NN to VX
     pub fn add_nn_to_x(x: u8, n2: u8, n3: u8) -> Self {
-        Self (0x7 << NIBBLE | x, n2 << NIBBLE | n3)
+        Instruction::AddNNToX { x, nn: n2 << NIBBLE | n3 }.into()
     }
 
     // Sets VX to the value of VY
     pub fn set_x_to_y(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0x0)
+        Instruction::SetXToY { x, y }.into()
     }
 
     // Sets VX to VX or VY
     pub fn or(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0x1)
+        Instruction::Or { x, y }.into()
     }
 
     // Sets VX to VX and VY
     pub fn and(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0x2)
+        Instruction::And { x, y }.into()
     }
 
     // Sets VX to VX xor VY
     pub fn xor(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0x3)
+        Instruction::Xor { x, y }.into()
     }
 
     // Adds VY to VX. VF is set to 1 when there's a carry, and to 0 when there is not
     pub fn add(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0x4)
+        Instruction::AddXY { x, y }.into()
     }
 
     // VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there is not
     pub fn sub(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0x5)
+        Instruction::SubXY { x, y }.into()
     }
 
     // Stores the least significant bit of VX in VF and then shifts VX to the right by 1
     pub fn shift_right(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0x6)
+        Instruction::ShiftRight { x, y }.into()
     }
 
     // Sets VX to VY minus VX. VF is set to 0 when there's a borrow, and 1 when there is not
     pub fn sub_x(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0x7)
+        Instruction::SubYX { x, y }.into()
     }
 
     // Stores the most significant bit of VX in VF and then shifts VX to the left by 1
     pub fn shift_left(x: u8, y: u8) -> Self {
-        Self (0x8 << NIBBLE | x, (y << NIBBLE) | 0xE)
+        Instruction::ShiftLeft { x, y }.into()
     }
     // Skips the next instruction if VX does not equal VY
     pub fn skip_x_neq_y(x: u8, y: u8) -> Self {
-        Self (0x9 << NIBBLE | x, y << NIBBLE | 0x0)
+        Instruction::SkipXNeqY { x, y }.into()
     }
 
     // Sets I to the address NNN
     pub fn set_i_to_nnn(n1: u8, n2: u8, n3: u8) -> Self {
-        Self (0xA << NIBBLE | n1, n2 << NIBBLE | n3)
+        Instruction::SetIToNNN { nnn: Address (n1, n2, n3) }.into()
     }
 
     // Jumps to the address NNN plus V0
     pub fn jump_to_nnn_plus_v0(n1: u8, n2: u8, n3: u8) -> Self {
-        Self (0xB << NIBBLE | n1, n2 << NIBBLE | n3)
+        Instruction::JumpToNNNPlusV0 { nnn: Address (n1, n2, n3) }.into()
+    }
+
+    // Colours the block of zones given by VX and VX+1 in the colour in VY (CHIP-8X)
+    pub fn set_zone_colour(x: u8, y: u8) -> Self {
+        Instruction::SetZoneColour { x, y }.into()
+    }
+
+    // Colours N rows of zones from the pixel at (VX, VX+1) in the colour in VY (CHIP-8X)
+    pub fn set_row_colour(x: u8, y: u8, n3: u8) -> Self {
+        Instruction::SetRowColour { x, y, n: n3 }.into()
     }
 
     // Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN
     pub fn rand(x: u8, n2: u8, n3: u8) -> Self {
-        Self (0xC << NIBBLE | x, n2 << NIBBLE | n3)
+        Instruction::Rand { x, nn: n2 << NIBBLE | n3 }.into()
     }
 
     // Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and a height of N pixels.
     // Each row of 8 pixels is read as bit-coded starting from memory location I;
     // I value does not change after the execution of this instruction. As described above, VF is
     // set to 1 if any screen pixels are flipped from set to unset when the sprite is drawn,
-    // and to 0 if that does not happen
+    // and to 0 if that does not happen. In SUPER-CHIP mode a height of 0 draws a 16x16 sprite
     pub fn draw(x: u8, y: u8, n3: u8) -> Self {
-        Self (0xD << NIBBLE | x, y << NIBBLE | n3)
+        Instruction::Draw { x, y, n: n3 }.into()
     }
 
     // Skips the next instruction if the key stored in VX is pressed
     pub fn skip_if_key(x: u8) -> Self {
-        Self (0xE << NIBBLE | x, 0x9E)
+        Instruction::SkipIfKey { x }.into()
     }
 
     // Skips the next instruction if the key stored in VX is not pressed
     pub fn skip_if_nkey(x: u8) -> Self {
-        Self (0xE << NIBBLE | x, 0xA1)
+        Instruction::SkipIfNotKey { x }.into()
+    }
+
+    // Skips the next instruction if the key stored in VX is pressed on the second keypad (CHIP-8X)
+    pub fn skip_if_key2(x: u8) -> Self {
+        Instruction::SkipIfKey2 { x }.into()
+    }
+
+    // Skips the next instruction if the key stored in VX is not pressed on the second keypad (CHIP-8X)
+    pub fn skip_if_nkey2(x: u8) -> Self {
+        Instruction::SkipIfNotKey2 { x }.into()
     }
 
     // Sets VX to the value of the delay timer
     pub fn set_x_to_timer(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x07)
+        Instruction::SetXToTimer { x }.into()
     }
 
     // A key press is awaited, and then stored in VX. (Blocking Operation)
     pub fn await_key(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x0A)
+        Instruction::AwaitKey { x }.into()
     }
 
     // Sets the delay timer to VX
     pub fn set_timer_to_x(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x15)
+        Instruction::SetTimerToX { x }.into()
     }
 
     // Sets the sound timer to VX
     pub fn set_sound_timer(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x18)
+        Instruction::SetSoundTimer { x }.into()
     }
 
     // Adds VX to I. VF is not affected
     pub fn add_x_to_i(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x1E)
+        Instruction::AddXToI { x }.into()
     }
 
     // Sets I to the location of the sprite for the character in VX.
     // Characters 0-F (in hexadecimal) are represented by a 4x5 font.
     pub fn set_i_to_sprite_addr(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x29)
+        Instruction::SetIToSpriteAddr { x }.into()
+    }
+
+    // Sets I to the location of the large sprite for the character in VX.
+    // Characters 0-F are represented by an 8x10 font (SUPER-CHIP)
+    pub fn set_i_to_large_sprite_addr(x: u8) -> Self {
+        Instruction::SetIToLargeSpriteAddr { x }.into()
     }
 
     // Stores the binary-coded decimal representation of VX, with the most significant of
@@ -204,18 +297,48 @@ impl OpCode {
     // hundreds digit in memory at location in I, the tens digit at location I+1, and the ones
     // digit at location I+2.);
     pub fn store_bcd(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x33)
+        Instruction::StoreBcd { x }.into()
+    }
+
+    // Sets I to the 16 bit address in the word that follows, which must be written after it (XO-CHIP)
+    pub fn set_i_to_long() -> Self {
+        Instruction::SetIToLong.into()
+    }
+
+    // Selects the bitplanes that drawing, clearing and scrolling apply to, plane 1 as bit 0 of N (XO-CHIP)
+    pub fn select_planes(n: u8) -> Self {
+        Instruction::SelectPlanes { n }.into()
+    }
+
+    // Loads the 16 byte (128 bit) audio pattern at I into the pattern buffer (XO-CHIP)
+    pub fn load_audio_pattern() -> Self {
+        Instruction::LoadAudioPattern.into()
+    }
+
+    // Sets the pitch register to VX, which sets the pattern's playback rate (XO-CHIP)
+    pub fn set_pitch(x: u8) -> Self {
+        Instruction::SetPitch { x }.into()
     }
 
     // Stores from V0 to VX (including VX) in memory, starting at address I.
     // The offset from I is increased by 1 for each value written, but I itself is left unmodified
     pub fn store_0_to_x_to_mem(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x55)
+        Instruction::StoreRegisters { x }.into()
     }
 
     // ills from V0 to VX (including VX) with values from memory, starting at address I.
     // The offset from I is increased by 1 for each value written, but I itself is left unmodified
     pub fn fill_0_to_x_to_mem(x: u8) -> Self {
-        Self (0xF << NIBBLE | x, 0x65)
+        Instruction::LoadRegisters { x }.into()
+    }
+
+    // Stores V0 to VX (including VX) in the RPL user flags, which outlive the program (SUPER-CHIP)
+    pub fn save_flags(x: u8) -> Self {
+        Instruction::SaveFlags { x }.into()
+    }
+
+    // Fills V0 to VX (including VX) from the RPL user flags (SUPER-CHIP)
+    pub fn load_flags(x: u8) -> Self {
+        Instruction::LoadFlags { x }.into()
     }
 }
@@ -0,0 +1,103 @@
+use super::font::{Font, DEFAULT_FONT_BASE};
+use super::processor::{Mode, PROGRAM_START};
+use super::quirks::Quirks;
+
+// A machine CHIP-8 programs were written for. Each one bundles the instruction set,
+// memory size, speed, font and quirks that its ROMs expect; see `CPU::set_platform`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Platform {
+    #[default]
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+    Chip8X,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 5] = [
+        Platform::CosmacVip,
+        Platform::Chip48,
+        Platform::SuperChip,
+        Platform::XoChip,
+        Platform::Chip8X,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xo-chip",
+            Platform::Chip8X => "chip8x",
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        match self {
+            Platform::CosmacVip | Platform::Chip48 => Mode::Chip8,
+            Platform::SuperChip => Mode::SuperChip,
+            Platform::XoChip => Mode::XoChip,
+            Platform::Chip8X => Mode::Chip8X,
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::CosmacVip | Platform::Chip8X => Quirks::vip(),
+            Platform::Chip48 => Quirks::chip48(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    // Roughly how fast the original ran. The HP48 interpreters were faster than the VIP,
+    // and Octo runs XO-CHIP programs at whatever speed they were tuned for, typically high.
+    pub fn instructions_per_frame(&self) -> u32 {
+        match self {
+            Platform::CosmacVip | Platform::Chip8X => 15,
+            Platform::Chip48 | Platform::SuperChip => 30,
+            Platform::XoChip => 1000,
+        }
+    }
+
+    // Only SUPER-CHIP and XO-CHIP had the large digits
+    pub fn font(&self) -> Font {
+        match self {
+            Platform::CosmacVip | Platform::Chip48 | Platform::Chip8X => Font::chip8(DEFAULT_FONT_BASE),
+            Platform::SuperChip | Platform::XoChip => Font::superchip(DEFAULT_FONT_BASE),
+        }
+    }
+
+    // CHIP-8X kept its colour routines below 0x300
+    pub fn program_start(&self) -> u16 {
+        match self {
+            Platform::Chip8X => 0x300,
+            _ => PROGRAM_START,
+        }
+    }
+
+    // The VIP based machines can run 1802 machine code routines
+    pub fn has_cdp1802(&self) -> bool {
+        matches!(self, Platform::CosmacVip | Platform::Chip8X)
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.mode().memory_size()
+    }
+}
+
+impl std::str::FromStr for Platform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "vip" | "cosmac-vip" | "chip8" | "chip-8" => Ok(Platform::CosmacVip),
+            "chip48" | "chip-48" => Ok(Platform::Chip48),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xo-chip" | "xochip" => Ok(Platform::XoChip),
+            "chip8x" | "chip-8x" => Ok(Platform::Chip8X),
+            _ => Err(format!("unknown platform '{}'", name)),
+        }
+    }
+}
@@ -1,6 +1,40 @@
-use std::convert::{From, TryFrom};
-use super::opcodes::{BYTE, NIBBLE, OPCODELENGTH, OpCode};
+use std::ops::Range;
+use std::time::Duration;
+use super::opcodes::{OPCODELENGTH, OpCode};
 use super::address::Address;
+use super::timers::{Clock, Timers};
+use super::display::{ColourMap, Display, EdgeMode, DISPLAY_HEIGHT, DISPLAY_WIDTH, HIRES_HEIGHT, HIRES_WIDTH, LARGE_SPRITE_WIDTH, SPRITE_WIDTH, ZONE_WIDTH};
+use super::keypad::Keypad;
+use super::font::Font;
+use super::rand::{self, RandomSource, XorShift};
+use super::error::{CpuError, DecodeError};
+use super::instruction::Instruction;
+use super::recording::Recording;
+use super::audio::{Audio, Voice, PATTERN_LENGTH};
+use super::quirks::Quirks;
+use super::platform::Platform;
+use super::cdp1802::{Cdp1802, ROUTINE_STEP_LIMIT};
+
+// Where programs are loaded and start running unless `CPU::program_start` says otherwise
+pub const PROGRAM_START: u16 = 0x200;
+
+// Scrolling left or right always moves the picture this many pixels
+pub const SCROLL_COLUMNS: usize = 4;
+
+// Where the VIP interpreter keeps V0-VF, its stack and the display buffer, which machine
+// code routines work on directly
+pub const VIP_REGISTERS: usize = 0xEF0;
+pub const VIP_STACK: u16 = 0xECF;
+pub const VIP_DISPLAY: usize = 0xF00;
+// The VIP interpreter's work area, from below its stack to the end of the display buffer.
+// Programs may not load into it while an 1802 is attached.
+pub const VIP_RESERVED: Range<usize> = 0xEA0..0x1000;
+
+// BXY0 colours CHIP-8X zones in blocks this many rows high
+pub const ZONE_BLOCK_HEIGHT: usize = 4;
+
+pub const MEMORY_SIZE: usize = 0x1000;
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
 
 back_to_enum! {
     enum NamedRegister {
@@ -8,157 +42,508 @@ back_to_enum! {
     }
 }
 
+// The instruction set the interpreter runs. Outside their own mode the extension
+// instructions behave as on the original interpreter: the 0NNN ones are machine code
+// routines and the rest are illegal.
+// XO-CHIP includes everything SUPER-CHIP added. CHIP-8X instead adds colour and a second
+// keypad to the original, giving up BNNN.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Mode {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+    Chip8X,
+}
+
+impl Mode {
+    pub fn supports(&self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::ScrollDown { .. }
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::SaveFlags { .. }
+            | Instruction::LoadFlags { .. } => self.has_superchip(),
+            Instruction::ScrollUp { .. }
+            | Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::SetIToLong
+            | Instruction::SelectPlanes { .. }
+            | Instruction::LoadAudioPattern
+            | Instruction::SetPitch { .. } => *self == Mode::XoChip,
+            Instruction::CycleBackground
+            | Instruction::SetZoneColour { .. }
+            | Instruction::SetRowColour { .. }
+            | Instruction::SkipIfKey2 { .. }
+            | Instruction::SkipIfNotKey2 { .. } => *self == Mode::Chip8X,
+            Instruction::JumpToNNNPlusV0 { .. } => *self != Mode::Chip8X,
+            _ => true,
+        }
+    }
+
+    pub fn has_superchip(&self) -> bool {
+        matches!(self, Mode::SuperChip | Mode::XoChip)
+    }
+
+    pub fn decode(&self, word: u16) -> Result<Instruction, DecodeError> {
+        match self {
+            Mode::Chip8X => Instruction::decode_chip8x(word),
+            _ => Instruction::decode(word),
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Mode::Chip8 | Mode::SuperChip | Mode::Chip8X => MEMORY_SIZE,
+            Mode::XoChip => XO_CHIP_MEMORY_SIZE,
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub struct CPU {
     pub registers: [u8; 16],
     pub program_counter: Address,
     pub i: u16,
-    pub memory: [u8; 0x1000],
+    pub memory: Vec<u8>,
+    pub program_start: u16,
     pub stack: [Address; 16],
     pub stack_pointer: usize,
+    pub timers: Timers,
+    pub clock: Clock,
+    pub display: Display,
+    pub keypad: Keypad,
+    // CHIP-8X's second keypad, read by EXF2 and EXF5
+    pub keypad2: Keypad,
+    pub font: Font,
+    pub rng: Box<dyn RandomSource>,
+    // Change with `set_mode` to size memory to match
+    pub mode: Mode,
+    // The HP48's RPL user flags, saved and loaded by FX75 and FX85. They are kept over a
+    // reset, as they outlived the program on the calculator
+    pub flags: [u8; 16],
+    // Behaviour of the instructions interpreters disagree on, kept over a reset. DXYN clips
+    // or wraps sprites as these say, whatever the display's own edge mode
+    pub quirks: Quirks,
+    // Captures the display on every timer tick while set
+    pub recording: Option<Recording>,
+    // XO-CHIP audio pattern and pitch
+    pub voice: Voice,
+    // Renders the buzzer on every timer tick while set
+    pub audio: Option<Audio>,
+    // Runs 0NNN machine code routines while set, otherwise `routine_fallback` decides
+    pub cdp1802: Option<Cdp1802>,
+    pub routine_fallback: RoutineFallback,
 }
 
-type DecodedOpcode = (u8, u8, u8, u8);
-
-#[derive(Debug, Clone, Copy)]
-pub struct ByteConstant (u8, u8);
+// What 0NNN does when there is no 1802 to run the routine
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RoutineFallback {
+    // Carry on with the next instruction
+    Ignore,
+    Error,
+    // Call NNN as a CHIP-8 subroutine, as 2NNN would
+    #[default]
+    Call,
+}
 
-impl From<(&u8, &u8)> for ByteConstant {
+impl std::str::FromStr for RoutineFallback {
+    type Err = String;
 
-    fn from(nibbles: (&u8, &u8)) -> Self {
-        Self (nibbles.0.clone(), nibbles.1.clone())
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ignore" => Ok(RoutineFallback::Ignore),
+            "error" => Ok(RoutineFallback::Error),
+            "call" => Ok(RoutineFallback::Call),
+            _ => Err(format!("unknown routine fallback '{}'", name)),
+        }
     }
+}
 
+// The outcome of executing a single instruction fetched from `address`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Executed { address: Address, opcode: u16 },
+    // FX0A is blocked on the keypad, the program counter stays on it
+    Waiting { address: Address },
+    Halted { address: Address },
 }
 
-impl From<ByteConstant> for u8 {
+impl Step {
+    pub fn is_halted(&self) -> bool {
+        matches!(self, Step::Halted { .. })
+    }
+}
 
-    fn from(constant: ByteConstant) -> Self {
-        constant.0 << NIBBLE | constant.1
+// How a bounded run ended: the number of instructions stepped, and whether the last
+// of them was the halt instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Run {
+    pub cycles: usize,
+    pub halted: bool,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct NibbleConstant (u8);
+impl CPU {
+    // A reset machine with the default configuration and the program counter on `PROGRAM_START`
+    pub fn new() -> Self {
+        let mut cpu = Self {
+            registers: [0; 16],
+            program_counter: Address (0, 0, 0),
+            i: 0,
+            memory: vec![0; MEMORY_SIZE],
+            program_start: PROGRAM_START,
+            stack: [Address (0, 0, 0); 16],
+            stack_pointer: 0,
+            timers: Timers::default(),
+            clock: Clock::default(),
+            display: Display::default(),
+            keypad: Keypad::default(),
+            keypad2: Keypad::default(),
+            font: Font::default(),
+            rng: Box::new(XorShift::new(rand::DEFAULT_SEED)),
+            mode: Mode::default(),
+            flags: [0; 16],
+            quirks: Quirks::default(),
+            recording: None,
+            voice: Voice::default(),
+            audio: None,
+            cdp1802: None,
+            routine_fallback: RoutineFallback::default(),
+        };
+
+        cpu.reset().expect("the default font fits in memory");
+        cpu
+    }
 
-impl From<&u8> for NibbleConstant {
+    pub fn with_mode(mode: Mode) -> Self {
+        let mut cpu = Self::new();
+        cpu.set_mode(mode).expect("the default font fits in every mode");
+        cpu
+    }
 
-    fn from(nibble: &u8) -> Self {
-        Self (nibble.clone())
+    pub fn with_platform(platform: Platform) -> Self {
+        let mut cpu = Self::new();
+        cpu.set_platform(platform).expect("platform fonts fit in memory");
+        cpu
     }
 
-}
+    pub fn with_rom(rom: &[u8]) -> Result<Self, CpuError> {
+        let mut cpu = Self::new();
+        cpu.load_rom(rom)?;
+
+        Ok(cpu)
+    }
 
-impl From<NibbleConstant> for u8 {
+    // Resets the machine and copies `rom` to `program_start`, where execution will begin.
+    // With an 1802 attached the ROM must stay clear of `VIP_RESERVED`.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), CpuError> {
+        let start = self.program_start as usize;
+        let end = match self.cdp1802 {
+            Some(_) if start < VIP_RESERVED.end => VIP_RESERVED.start,
+            _ => self.memory.len(),
+        };
+        let available = end.saturating_sub(start);
+
+        if rom.len() > available {
+            return Err(CpuError::RomTooLarge { length: rom.len(), start: self.program_start, available });
+        }
 
-    fn from(constant: NibbleConstant) -> Self {
-        constant.0
+        self.reset()?;
+        self.raw_copy_to_mem(start, rom)
     }
-}
-impl CPU {
-    pub fn copy_to_mem(&mut self, loc: usize, data: &[OpCode]) {
-        data.iter().fold(loc, |loc, bytes| {
+
+    // Switches instruction set, resizing memory for it, and resets the machine. Fails like
+    // `reset` if the font no longer fits.
+    pub fn set_mode(&mut self, mode: Mode) -> Result<(), CpuError> {
+        self.mode = mode;
+        self.memory = vec![0; mode.memory_size()];
+        self.reset()
+    }
+
+    // Configures the machine as `platform`: its mode and memory, quirks, speed, font, load
+    // address and whether it has an 1802 for machine code. Resets the machine like `set_mode`,
+    // and fails the same way.
+    pub fn set_platform(&mut self, platform: Platform) -> Result<(), CpuError> {
+        self.cdp1802 = if platform.has_cdp1802() { Some(Cdp1802::new()) } else { None };
+        self.quirks = platform.quirks();
+        self.clock.instructions_per_frame = platform.instructions_per_frame();
+        self.font = platform.font();
+        self.program_start = platform.program_start();
+        self.set_mode(platform.mode())
+    }
+
+    // Clears memory, registers, the stack, timers and the screen, returns to low resolution,
+    // installs the font and points the program counter at `program_start`.
+    // Configuration such as the mode, quirks, clock rate and font choice is kept. Fails with
+    // a memory fault if the font does not fit in memory.
+    pub fn reset(&mut self) -> Result<(), CpuError> {
+        self.registers = [0; 16];
+        self.program_counter = self.program_start.into();
+        self.i = 0;
+        self.memory.iter_mut().for_each(|byte| *byte = 0);
+        self.stack = [Address (0, 0, 0); 16];
+        self.stack_pointer = 0;
+        self.timers = Timers::default();
+        self.voice = Voice::default();
+        self.clock.frames = 0;
+        self.display.resize(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        self.display.selected_planes = 1;
+        self.display.colours = match self.mode {
+            Mode::Chip8X => Some(ColourMap::new(DISPLAY_WIDTH, DISPLAY_HEIGHT)),
+            _ => None,
+        };
+        self.keypad.reset();
+        self.keypad2.reset();
+        if let Some(cdp1802) = &mut self.cdp1802 {
+            *cdp1802 = Cdp1802::new();
+        }
+        self.font.install(&mut self.memory)
+    }
+
+    pub fn copy_to_mem(&mut self, loc: usize, data: &[OpCode]) -> Result<(), CpuError> {
+        data.iter().try_fold(loc, |loc, bytes| {
             self.add_to_mem(loc, bytes)
-        });
-    }
-
-    pub fn raw_copy_to_mem(&mut self, loc: usize, data: &[u8]) {
-        data.chunks(2).fold(loc, |loc, bytes| {
-            self.raw_add_to_mem(loc, *bytes.first().unwrap(), *bytes.last().unwrap())
-        });
-    }
-
-    pub fn add_to_mem(&mut self, loc: usize, oc: &OpCode) -> usize {
-        self.memory[loc] = oc.high_byte(); self.memory[loc + 1] = oc.low_byte();
-        loc + 2
-    }
-
-    pub fn raw_add_to_mem(&mut self, loc: usize, high: u8, low: u8) -> usize {
-        self.memory[loc] = high; self.memory[loc + 1] = low;
-        loc + 2
-    }
-    
-    fn read_opcode(&self) -> u16 {
-        let pc: usize = self.program_counter.into();
-        let op_byte1 = self.memory[pc] as u16;
-        let op_byte2 = self.memory[pc + 1] as u16;
-
-        op_byte1 << 8 | op_byte2
-    }
-
-    fn decode(&mut self, code: u16) -> DecodedOpcode {
-        (
-            ((code & 0xF000 ) >> 12 ) as u8, // c
-            ((code & 0x0F00 ) >>  8 ) as u8, // x
-            ((code & 0x00F0 ) >>  4 ) as u8, // y
-            ((code & 0x000F ) >>  0 ) as u8, // d
-        )
-    }
-
-    pub fn run(&mut self) {
-       'execution: loop {
-            let code = self.read_opcode();
-            let opcode = self.decode(code);
-            self.program_counter += OPCODELENGTH;
-
-            match &opcode.into() {
-                (0x0, 0x0, 0x0, 0x0) => break 'execution, // halt
-                (0x0,0x0, 0x0, 0xE) => unimplemented!(), // clear
-                (0x0, 0x0, 0xE, 0xE) => self.ret(), // return
-                (0x1, n1, n2, n3) => self.goto((n1, n2, n3).into()), // goto
-                (0x2, n1, n2, n3) => self.call((n1, n2, n3).into()),
-                (0x0, n1, n2, n3) => self.call((n1, n2, n3).into()), // call routine
-                (0x3, x, n2, n3)  => self.skip_x_eq_nn (&(*x as usize), (n2, n3).into()), // skip if X equals NN
-                (0x4, x, n2, n3)  => self.skip_x_neq_nn(&(*x as usize), (n2, n3).into()), // skip if X not equals NN
-                (0x5, x, y, 0x0)  => self.skip_x_eq_y(&(*x as usize), &(*y as usize)), // skip if X equals Y
-                (0x6, x, n2, n3)  => self.set_x_to_nn(&(*x as usize), (n2, n3).into()), // set x to NN
-                (0x7, x, n2, n3)  => self.add_nn_to_x(&(*x as usize), (n2, n3).into()), // add NN to x
-                (0x8, x, y, 0x0) => self.set_xy(&(*x as usize), &(*y as usize)),
-                (0x8, x, y, 0x1) => self.or_xy(&(*x as usize), &(*y as usize)),
-                (0x8, x, y, 0x2) => self.and_xy(&(*x as usize), &(*y as usize)),
-                (0x8, x, y, 0x3) => self.xor_xy(&(*x as usize), &(*y as usize)),
-                (0x8, x, y, 0x4) => self.add_xy(&(*x as usize), &(*y as usize)),
-                (0x8, x, y, 0x5) => self.sub_xy(&(*x as usize), &(*y as usize)),
-                (0x8, x, y, 0x6) => self.shift_right(&(*x as usize), &(*y as usize)),
-                (0x8, x, y, 0x7) => self.sub_yx(&(*x as usize), &(*y as usize)),
-                (0x8, x, y, 0xE) => self.shift_left(&(*x as usize), &(*y as usize)),
-                (0x9, x, y, 0x0) => self.skip_x_neq_y(&(*x as usize), &(*y as usize)), // skip if x not equal to y
-                (0xA, n1, n2, n3) => self.set_i_to_nnn((n1, n2, n3).into()),
-                (0xB, n1, n2, n3) => unimplemented!(),
-                (0xC, n1, n2, n3) => unimplemented!(),
-                (0xD, n1, n2, n3) => unimplemented!(),
-                (0xE, x, 0x9, 0xE) => unimplemented!(),
-                (0xF, x, 0x0, 0x7) => unimplemented!(),
-                (0xF, x, 0x0, 0xA) => unimplemented!(),
-                (0xF, x, 0x1, 0x5) => unimplemented!(),
-                (0xF, x, 0x1, 0x8) => unimplemented!(),
-                (0xF, x, 0x1, 0xE) => unimplemented!(),
-                (0xF, x, 0x2, 0x9) => unimplemented!(),
-                (0xF, x, 0x3, 0x3) => unimplemented!(),
-                (0xF, x, 0x5, 0x5) => unimplemented!(),
-                (0xF, x, 0x6, 0x5) => unimplemented!(),
-                _ => todo!("opcode {:04x}", code),
+        })?;
+
+        Ok(())
+    }
+
+    pub fn raw_copy_to_mem(&mut self, loc: usize, data: &[u8]) -> Result<(), CpuError> {
+        let range = self.mem_range(loc, data.len())?;
+        self.memory[range].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    pub fn add_to_mem(&mut self, loc: usize, oc: &OpCode) -> Result<usize, CpuError> {
+        self.raw_add_to_mem(loc, oc.high_byte(), oc.low_byte())
+    }
+
+    pub fn raw_add_to_mem(&mut self, loc: usize, high: u8, low: u8) -> Result<usize, CpuError> {
+        let range = self.mem_range(loc, OPCODELENGTH)?;
+        self.memory[range].copy_from_slice(&[high, low]);
+
+        Ok(loc + OPCODELENGTH)
+    }
+
+    // Checks that `length` bytes starting at `loc` all lie within memory
+    fn mem_range(&self, loc: usize, length: usize) -> Result<Range<usize>, CpuError> {
+        match loc.checked_add(length) {
+            Some(end) if end <= self.memory.len() => Ok(loc..end),
+            _ => Err(CpuError::MemoryFault { location: loc, length }),
+        }
+    }
+
+    fn read_opcode(&self) -> Result<u16, CpuError> {
+        let range = self.mem_range(self.program_counter.into(), OPCODELENGTH)?;
+        let bytes = &self.memory[range];
+
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng.seed(seed);
+    }
+
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        while !self.step()?.is_halted() {}
+
+        Ok(())
+    }
+
+    // Steps at most `cycles` instructions, stopping early on halt
+    pub fn run_for(&mut self, cycles: usize) -> Result<Run, CpuError> {
+        self.run_while(|_, count| count < cycles)
+    }
+
+    // Steps until `predicate` holds, checking it before each instruction so it can stop
+    // on an address before it executes. Also stops on halt.
+    pub fn run_until<P>(&mut self, mut predicate: P) -> Result<Run, CpuError>
+    where
+        P: FnMut(&CPU) -> bool,
+    {
+        self.run_while(|cpu, _| !predicate(cpu))
+    }
+
+    fn run_while<P>(&mut self, mut predicate: P) -> Result<Run, CpuError>
+    where
+        P: FnMut(&CPU, usize) -> bool,
+    {
+        let mut run = Run { cycles: 0, halted: false };
+
+        while !run.halted && predicate(self, run.cycles) {
+            run.halted = self.step()?.is_halted();
+            run.cycles += 1;
+        }
+
+        Ok(run)
+    }
+
+    // Runs one 60 Hz frame: the clock's instructions_per_frame, then a single timer tick.
+    // Returns false once the program has halted
+    pub fn run_frame(&mut self) -> Result<bool, CpuError> {
+        self.keypad.update(self.clock.frames);
+        self.keypad2.update(self.clock.frames);
+
+        for _ in 0..self.clock.instructions_per_frame {
+            match self.step()? {
+                Step::Halted { .. } => return Ok(false),
+                // With the display wait quirk a draw holds the rest of the frame up
+                Step::Executed { opcode, .. } if self.quirks.display_wait && opcode & 0xF000 == 0xD000 => break,
+                _ => (),
             }
         }
+
+        self.tick_timers();
+        Ok(true)
+    }
+
+    // Runs as many frames as the host time elapsed calls for
+    pub fn run_for_duration(&mut self, elapsed: Duration) -> Result<bool, CpuError> {
+        for _ in 0..self.clock.advance(elapsed) {
+            if !self.run_frame()? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub fn tick_timers(&mut self) {
+        if let Some(audio) = &mut self.audio {
+            audio.render_frame(self.timers.is_sounding(), &self.voice);
+        }
+
+        self.timers.tick();
+        self.clock.frames += 1;
+
+        if let Some(recording) = &mut self.recording {
+            recording.capture(&self.display);
+        }
+    }
+
+    // Starts capturing a frame per timer tick, discarding any recording in progress
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording::new());
+    }
+
+    // Ends the recording with one last capture of the display as it is now
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        let mut recording = self.recording.take()?;
+        recording.capture(&self.display);
+
+        Some(recording)
+    }
+
+    // Executes the instruction at the program counter
+    pub fn step(&mut self) -> Result<Step, CpuError> {
+        let address = self.program_counter;
+        let code = self.read_opcode()?;
+        let instruction = match self.mode.decode(code) {
+            Ok(instruction) if self.mode.supports(&instruction) => instruction,
+            Ok(_) if code & 0xF000 == 0 => Instruction::CallRoutine { nnn: Address::from(code) },
+            _ => return Err(CpuError::IllegalOpcode { opcode: code, address }),
+        };
+        self.program_counter += OPCODELENGTH;
+
+        match instruction {
+            Instruction::Halt | Instruction::Exit => return Ok(Step::Halted { address }),
+            Instruction::ScrollDown { n } => self.display.scroll_down(n as usize),
+            Instruction::ScrollUp { n } => self.display.scroll_up(n as usize),
+            Instruction::Clear => self.display.clear(),
+            Instruction::Return => self.ret(address)?,
+            Instruction::ScrollRight => self.display.scroll_right(SCROLL_COLUMNS),
+            Instruction::ScrollLeft => self.display.scroll_left(SCROLL_COLUMNS),
+            Instruction::LowRes => self.display.resize(DISPLAY_WIDTH, DISPLAY_HEIGHT),
+            Instruction::HighRes => self.display.resize(HIRES_WIDTH, HIRES_HEIGHT),
+            Instruction::Goto { nnn } => self.goto(nnn),
+            Instruction::Call { nnn } => self.call(address, nnn)?,
+            Instruction::CallRoutine { nnn } => self.call_routine(address, nnn)?,
+            Instruction::CycleBackground => self.cycle_background(),
+            Instruction::SkipXEqNN { x, nn } => self.skip_x_eq_nn(&(x as usize), nn),
+            Instruction::SkipXNeqNN { x, nn } => self.skip_x_neq_nn(&(x as usize), nn),
+            Instruction::SkipXEqY { x, y } => self.skip_x_eq_y(&(x as usize), &(y as usize)),
+            Instruction::StoreRange { x, y } => self.store_x_to_y_to_mem(&(x as usize), &(y as usize))?,
+            Instruction::LoadRange { x, y } => self.fill_x_to_y_from_mem(&(x as usize), &(y as usize))?,
+            Instruction::SetXToNN { x, nn } => self.set_x_to_nn(&(x as usize), nn),
+            Instruction::AddNNToX { x, nn } => self.add_nn_to_x(&(x as usize), nn),
+            Instruction::SetXToY { x, y } => self.set_xy(&(x as usize), &(y as usize)),
+            Instruction::Or { x, y } => self.or_xy(&(x as usize), &(y as usize)),
+            Instruction::And { x, y } => self.and_xy(&(x as usize), &(y as usize)),
+            Instruction::Xor { x, y } => self.xor_xy(&(x as usize), &(y as usize)),
+            Instruction::AddXY { x, y } => self.add_xy(&(x as usize), &(y as usize)),
+            Instruction::SubXY { x, y } => self.sub_xy(&(x as usize), &(y as usize)),
+            Instruction::ShiftRight { x, y } => self.shift_right(&(x as usize), &(y as usize)),
+            Instruction::SubYX { x, y } => self.sub_yx(&(x as usize), &(y as usize)),
+            Instruction::ShiftLeft { x, y } => self.shift_left(&(x as usize), &(y as usize)),
+            Instruction::SkipXNeqY { x, y } => self.skip_x_neq_y(&(x as usize), &(y as usize)),
+            Instruction::SetIToNNN { nnn } => self.set_i_to_nnn(nnn),
+            Instruction::JumpToNNNPlusV0 { nnn } => self.jump_plus_register(nnn),
+            Instruction::SetZoneColour { x, y } => self.set_zone_colour(&(x as usize), &(y as usize)),
+            Instruction::SetRowColour { x, y, n } => self.set_row_colour(&(x as usize), &(y as usize), n),
+            Instruction::Rand { x, nn } => self.rand(&(x as usize), nn),
+            Instruction::Draw { x, y, n } => self.draw(&(x as usize), &(y as usize), n)?,
+            Instruction::SkipIfKey { x } => self.skip_if_key(&(x as usize)),
+            Instruction::SkipIfNotKey { x } => self.skip_if_nkey(&(x as usize)),
+            Instruction::SkipIfKey2 { x } => self.skip_if_key2(&(x as usize)),
+            Instruction::SkipIfNotKey2 { x } => self.skip_if_nkey2(&(x as usize)),
+            Instruction::SetIToLong => self.set_i_to_long()?,
+            Instruction::SelectPlanes { n } => self.display.selected_planes = n,
+            Instruction::LoadAudioPattern => self.load_audio_pattern()?,
+            Instruction::SetXToTimer { x } => self.set_x_to_timer(&(x as usize)),
+            Instruction::AwaitKey { x } => if !self.await_key(&(x as usize)) {
+                return Ok(Step::Waiting { address });
+            },
+            Instruction::SetTimerToX { x } => self.set_timer_to_x(&(x as usize)),
+            Instruction::SetSoundTimer { x } => self.set_sound_timer(&(x as usize)),
+            Instruction::AddXToI { x } => self.add_x_to_i(&(x as usize)),
+            Instruction::SetIToSpriteAddr { x } => self.set_i_to_sprite_addr(&(x as usize)),
+            Instruction::SetIToLargeSpriteAddr { x } => self.set_i_to_large_sprite_addr(&(x as usize)),
+            Instruction::StoreBcd { x } => self.store_bcd(&(x as usize))?,
+            Instruction::SetPitch { x } => self.set_pitch(&(x as usize)),
+            Instruction::StoreRegisters { x } => self.store_0_to_x_to_mem(&(x as usize))?,
+            Instruction::LoadRegisters { x } => self.fill_0_to_x_from_mem(&(x as usize))?,
+            Instruction::SaveFlags { x } => self.save_flags(&(x as usize)),
+            Instruction::LoadFlags { x } => self.load_flags(&(x as usize)),
+        }
+
+        Ok(Step::Executed { address, opcode: code })
     }
 
     fn set_xy(&mut self, x: &usize, y: &usize) {
         self.registers[*x] = self.registers[*y];
     }
 
-    // I'm not sure if this is correct or not...
     fn or_xy(&mut self, x: &usize, y: &usize) {
-        self.registers[*x] = self.registers[*x as usize] | self.registers[*y];
+        self.registers[*x] |= self.registers[*y];
+        self.reset_flag_after_logic();
     }
 
-    // I'm not sure if this is corret or not...
     fn and_xy(&mut self, x: &usize, y: &usize) {
-        self.registers[*x] = self.registers[*x as usize] & self.registers[*y];
+        self.registers[*x] &= self.registers[*y];
+        self.reset_flag_after_logic();
     }
 
-    // I'm not sure if this is correct or not...
     fn xor_xy(&mut self, x: &usize, y: &usize) {
-        self.registers[*x] = self.registers[*x as usize] ^ self.registers[*y];
+        self.registers[*x] ^= self.registers[*y];
+        self.reset_flag_after_logic();
+    }
+
+    // The VIP's logic routines happened to leave VF cleared
+    fn reset_flag_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[NamedRegister::Flag as usize] = 0;
+        }
     }
 
     fn add_xy(&mut self, x: &usize, y: &usize) {
@@ -166,7 +551,7 @@ impl CPU {
         let arg2 = self.registers[*y];
 
         let (val, overflow) = arg1.overflowing_add(arg2);
-        self.registers[*x as usize] = val;
+        self.registers[*x] = val;
         
         if overflow {
             self.registers[NamedRegister::Flag as usize] = 1;
@@ -179,74 +564,87 @@ impl CPU {
         let arg1 = self.registers[*x];
         let arg2 = self.registers[*y];
 
-        let (val, overflow) = arg1.overflowing_sub(arg2);
-        self.registers[*x as usize] = val;
-        
-        if overflow {
-            self.registers[NamedRegister::Flag as usize] = 1;
-        } else {
-            self.registers[NamedRegister::Flag as usize] = 0;
-        }
+        // VF is set when there is no borrow, and written last so it wins when X is F
+        let (val, borrow) = arg1.overflowing_sub(arg2);
+        self.registers[*x] = val;
+        self.registers[NamedRegister::Flag as usize] = !borrow as u8;
     }
 
-    fn  shift_right(&mut self, x: &usize, _y: &usize) {
-            self.registers[NamedRegister::Flag as usize] = (self.registers[*x as usize] >> 7) & 1;
-            self.registers[*x] = self.registers[*x as usize] >> 1;
+    // The bit shifted out ends up in VF
+    fn  shift_right(&mut self, x: &usize, y: &usize) {
+        let value = self.shift_source(x, y);
+        self.registers[*x] = value >> 1;
+        self.registers[NamedRegister::Flag as usize] = value & 1;
     }
 
     fn  sub_yx(&mut self, x: &usize, y: &usize) {
         let arg1 = self.registers[*x];
         let arg2 = self.registers[*y];
 
-        let (val, overflow) = arg2.overflowing_sub(arg1);
-        self.registers[*x as usize] = val;
+        let (val, borrow) = arg2.overflowing_sub(arg1);
+        self.registers[*x] = val;
+        self.registers[NamedRegister::Flag as usize] = !borrow as u8;
+    }
 
-        if overflow {
-            self.registers[NamedRegister::Flag as usize] = 1;
-        } else {
-            self.registers[NamedRegister::Flag as usize] = 0;
-        }
+    fn  shift_left(&mut self, x: &usize, y: &usize) {
+        let value = self.shift_source(x, y);
+        self.registers[*x] = value << 1;
+        self.registers[NamedRegister::Flag as usize] = value >> 7;
     }
 
-    fn  shift_left(&mut self, x: &usize, _y: &usize) {
-            self.registers[NamedRegister::Flag as usize] = self.registers[*x] & 1;
-            self.registers[*x] = self.registers[*x] << 1;
+    fn shift_source(&self, x: &usize, y: &usize) -> u8 {
+        if self.quirks.shift_uses_vy { self.registers[*y] } else { self.registers[*x] }
+    }
+
+    // Skips the next instruction, all four bytes of it for XO-CHIP's F000 NNNN
+    fn skip(&mut self) {
+        let next: usize = self.program_counter.into();
+        let long = self.mode == Mode::XoChip && self.memory.get(next..next + OPCODELENGTH) == Some(&[0xF0, 0x00][..]);
+
+        self.program_counter += if long { 2 * OPCODELENGTH } else { OPCODELENGTH };
     }
 
     fn goto(&mut self, addr: Address) {
-        self.program_counter = addr.into();
+        self.program_counter = addr;
     }
 
-    fn skip_x_eq_nn(&mut self, x: &usize, nn: ByteConstant) {
-        if self.registers[*x] == nn.into() {
-            self.program_counter += OPCODELENGTH;
+    // BNNN adds V0 to NNN, or with the jump quirk reads BXNN and adds VX
+    fn jump_plus_register(&mut self, nnn: Address) {
+        let nnn: u16 = nnn.into();
+        let x = if self.quirks.jump_uses_vx { (nnn >> 8) as usize } else { 0 };
+
+        self.goto(Address::from(nnn) + self.registers[x] as u16);
+    }
+
+    fn skip_x_eq_nn(&mut self, x: &usize, nn: u8) {
+        if self.registers[*x] == nn {
+            self.skip();
         }
     }
 
-    fn skip_x_neq_nn(&mut self, x: &usize, nn: ByteConstant) {
-        if self.registers[*x] != nn.into() {
-            self.program_counter += OPCODELENGTH;
+    fn skip_x_neq_nn(&mut self, x: &usize, nn: u8) {
+        if self.registers[*x] != nn {
+            self.skip();
         }
     }
 
     fn skip_x_eq_y(&mut self, x: &usize, y: &usize) {
         if self.registers[*x] == self.registers[*y] {
-            self.program_counter += OPCODELENGTH;
+            self.skip();
         }
     }
 
-    fn set_x_to_nn(&mut self, x: &usize, nn: ByteConstant) {
-        self.registers[*x] = nn.into();
+    fn set_x_to_nn(&mut self, x: &usize, nn: u8) {
+        self.registers[*x] = nn;
     }
 
-    fn add_nn_to_x(&mut self, x: &usize, nn: ByteConstant) {
-        let nn: u8 = nn.into();
-        self.registers[*x] = self.registers[*x] + nn;
+    fn add_nn_to_x(&mut self, x: &usize, nn: u8) {
+        self.registers[*x] = self.registers[*x].wrapping_add(nn);
     }
 
     fn skip_x_neq_y(&mut self, x: &usize, y: &usize) {
         if self.registers[*x] != self.registers[*y] {
-            self.program_counter += OPCODELENGTH;
+            self.skip();
         }
     }
 
@@ -254,23 +652,293 @@ impl CPU {
         self.i = addr.into()
     }
 
-    fn call(&mut self, addr: Address) {
-        if self.stack_pointer > self.stack.len() {
-            panic!("Stack Overflow!");
+    fn call(&mut self, address: Address, addr: Address) -> Result<(), CpuError> {
+        if self.stack_pointer >= self.stack.len() {
+            return Err(CpuError::StackOverflow { address });
         }
 
-        self.stack[self.stack_pointer] = self.program_counter.into();
+        self.stack[self.stack_pointer] = self.program_counter;
         self.stack_pointer += 1;
-        self.program_counter = addr.into();
+        self.program_counter = addr;
+
+        Ok(())
     }
 
-    fn ret(&mut self) {
+    fn call_routine(&mut self, address: Address, routine: Address) -> Result<(), CpuError> {
+        match self.routine_fallback {
+            _ if self.cdp1802.is_some() => self.run_machine_code(address, routine),
+            RoutineFallback::Ignore => Ok(()),
+            RoutineFallback::Error => Err(CpuError::UnsupportedRoutine { routine, address }),
+            RoutineFallback::Call => self.call(address, routine),
+        }
+    }
+
+    // Runs the routine on the 1802 with memory laid out as the VIP interpreter leaves it:
+    // V0-VF and a low resolution display in their places, I in RA and R2 as the stack
+    // pointer. Whatever the routine changes there is read back afterwards.
+    fn run_machine_code(&mut self, address: Address, routine: Address) -> Result<(), CpuError> {
+        let shares_display = self.display.width == DISPLAY_WIDTH && self.display.height == DISPLAY_HEIGHT;
+        let display_bytes = DISPLAY_WIDTH * DISPLAY_HEIGHT / 8;
+
+        self.memory[VIP_REGISTERS..VIP_REGISTERS + 16].copy_from_slice(&self.registers);
+        if shares_display {
+            let display = &self.display;
+            for (index, byte) in self.memory[VIP_DISPLAY..VIP_DISPLAY + display_bytes].iter_mut().enumerate() {
+                let (x, y) = (index % 8 * 8, index / 8);
+                *byte = (0..8).fold(0, |byte, bit| byte | (display.pixel(x + bit, y) as u8) << (7 - bit));
+            }
+        }
+
+        let cdp1802 = self.cdp1802.as_mut().expect("checked by call_routine");
+        cdp1802.r[0xA] = self.i;
+        cdp1802.r[2] = VIP_STACK;
+        cdp1802.x = 2;
+        if cdp1802.call(&mut self.memory, routine.into(), ROUTINE_STEP_LIMIT).is_none() {
+            return Err(CpuError::RoutineTimeout { routine, address });
+        }
+        self.i = cdp1802.r[0xA];
+
+        self.registers.copy_from_slice(&self.memory[VIP_REGISTERS..VIP_REGISTERS + 16]);
+        if shares_display {
+            for index in 0..display_bytes {
+                let (x, y) = (index % 8 * 8, index / 8);
+                for bit in 0..8 {
+                    self.display.set_pixel(x + bit, y, self.memory[VIP_DISPLAY + index] & (0x80 >> bit) != 0);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ret(&mut self, address: Address) -> Result<(), CpuError> {
         if self.stack_pointer == 0 {
-            panic!("Stack Underflow!")
+            return Err(CpuError::StackUnderflow { address });
         }
 
         self.stack_pointer -= 1;
         let call_addr = self.stack[self.stack_pointer];
-        self.program_counter = call_addr.into();
+        self.program_counter = call_addr;
+
+        Ok(())
+    }
+
+    fn rand(&mut self, x: &usize, nn: u8) {
+        self.registers[*x] = self.rng.next_byte(&self.memory) & nn;
+    }
+
+    // SUPER-CHIP draws a 16x16 sprite, 32 bytes, for a height of 0. XO-CHIP reads a sprite
+    // for each selected plane, one after the other.
+    fn draw(&mut self, x: &usize, y: &usize, rows: u8) -> Result<(), CpuError> {
+        let edge_mode = if self.quirks.clip_sprites { EdgeMode::Clip } else { EdgeMode::Wrap };
+        let large = rows == 0 && self.mode.has_superchip();
+        let planes = self.display.selected_planes.count_ones() as usize;
+        let length = if large { LARGE_SPRITE_WIDTH * 2 } else { rows as usize } * planes.max(1);
+        let range = self.mem_range(self.i as usize, length)?;
+        let sprite = &self.memory[range];
+
+        let x = self.registers[*x] as usize;
+        let y = self.registers[*y] as usize;
+        let width = if large { LARGE_SPRITE_WIDTH } else { SPRITE_WIDTH };
+        let collision = self.display.draw_wide_sprite(x, y, sprite, width, edge_mode);
+
+        self.registers[NamedRegister::Flag as usize] = collision as u8;
+
+        Ok(())
+    }
+
+    fn cycle_background(&mut self) {
+        if let Some(colours) = &mut self.display.colours {
+            colours.cycle_background();
+        }
+    }
+
+    // BXY0 colours a block of zones in VY's colour. VX holds the first zone column in its
+    // low nibble and how many more to colour in its high nibble, VX+1 the same for rows
+    // of 4 pixels.
+    fn set_zone_colour(&mut self, x: &usize, y: &usize) {
+        let (across, down) = (self.registers[*x], self.registers[(*x + 1) & 0xF]);
+        let colour = self.registers[*y];
+
+        if let Some(colours) = &mut self.display.colours {
+            for column in (across & 0xF)..=(across & 0xF) + (across >> 4) {
+                for block in (down & 0xF)..=(down & 0xF) + (down >> 4) {
+                    for row in 0..ZONE_BLOCK_HEIGHT {
+                        colours.set_foreground(column as usize, block as usize * ZONE_BLOCK_HEIGHT + row, colour);
+                    }
+                }
+            }
+        }
+    }
+
+    // BXYN colours the zone holding pixel (VX, VX+1) and those below it, N rows in all,
+    // in VY's colour
+    fn set_row_colour(&mut self, x: &usize, y: &usize, rows: u8) {
+        let (column, top) = (self.registers[*x] as usize / ZONE_WIDTH, self.registers[(*x + 1) & 0xF] as usize);
+        let colour = self.registers[*y];
+
+        if let Some(colours) = &mut self.display.colours {
+            for row in top..top + rows as usize {
+                colours.set_foreground(column, row, colour);
+            }
+        }
+    }
+
+    fn set_x_to_timer(&mut self, x: &usize) {
+        self.registers[*x] = self.timers.delay;
+    }
+
+    fn skip_if_key(&mut self, x: &usize) {
+        if self.keypad.is_pressed(self.registers[*x]) {
+            self.skip();
+        }
+    }
+
+    fn skip_if_nkey(&mut self, x: &usize) {
+        if !self.keypad.is_pressed(self.registers[*x]) {
+            self.skip();
+        }
+    }
+
+    fn skip_if_key2(&mut self, x: &usize) {
+        if self.keypad2.is_pressed(self.registers[*x]) {
+            self.skip();
+        }
+    }
+
+    fn skip_if_nkey2(&mut self, x: &usize) {
+        if !self.keypad2.is_pressed(self.registers[*x]) {
+            self.skip();
+        }
+    }
+
+    // Blocks by re-running this instruction until the keypad reports a key
+    fn await_key(&mut self, x: &usize) -> bool {
+        match self.keypad.awaited_key() {
+            Some(key) => {
+                self.registers[*x] = key;
+                true
+            }
+            None => {
+                self.program_counter -= OPCODELENGTH;
+                false
+            }
+        }
+    }
+
+    fn set_timer_to_x(&mut self, x: &usize) {
+        self.timers.delay = self.registers[*x];
+    }
+
+    fn set_sound_timer(&mut self, x: &usize) {
+        self.timers.sound = self.registers[*x];
+    }
+
+    fn add_x_to_i(&mut self, x: &usize) {
+        self.i = self.i.wrapping_add(self.registers[*x] as u16);
+    }
+
+    fn set_i_to_sprite_addr(&mut self, x: &usize) {
+        self.i = self.font.small_glyph_address(self.registers[*x]);
+    }
+
+    // Falls back to the small glyph when the font has no large one
+    fn set_i_to_large_sprite_addr(&mut self, x: &usize) {
+        let character = self.registers[*x];
+        self.i = self.font.large_glyph_address(character)
+            .unwrap_or_else(|| self.font.small_glyph_address(character));
+    }
+
+    // The address is the word after the instruction, which is skipped
+    fn set_i_to_long(&mut self) -> Result<(), CpuError> {
+        let range = self.mem_range(self.program_counter.into(), OPCODELENGTH)?;
+        let bytes = &self.memory[range];
+
+        self.i = (bytes[0] as u16) << 8 | bytes[1] as u16;
+        self.program_counter += OPCODELENGTH;
+        Ok(())
+    }
+
+    fn load_audio_pattern(&mut self) -> Result<(), CpuError> {
+        let range = self.mem_range(self.i as usize, PATTERN_LENGTH)?;
+        let mut pattern = [0; PATTERN_LENGTH];
+        pattern.copy_from_slice(&self.memory[range]);
+
+        self.voice.pattern = Some(pattern);
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, x: &usize) {
+        self.voice.pitch = self.registers[*x];
+    }
+
+    fn store_bcd(&mut self, x: &usize) -> Result<(), CpuError> {
+        let value = self.registers[*x];
+        let range = self.mem_range(self.i as usize, 3)?;
+
+        self.memory[range].copy_from_slice(&[value / 100, (value / 10) % 10, value % 10]);
+
+        Ok(())
+    }
+
+    fn store_0_to_x_to_mem(&mut self, x: &usize) -> Result<(), CpuError> {
+        let range = self.mem_range(self.i as usize, *x + 1)?;
+        self.memory[range].copy_from_slice(&self.registers[..=*x]);
+        self.step_i_past_registers(x);
+
+        Ok(())
+    }
+
+    fn fill_0_to_x_from_mem(&mut self, x: &usize) -> Result<(), CpuError> {
+        let range = self.mem_range(self.i as usize, *x + 1)?;
+        self.registers[..=*x].copy_from_slice(&self.memory[range]);
+        self.step_i_past_registers(x);
+
+        Ok(())
+    }
+
+    fn step_i_past_registers(&mut self, x: &usize) {
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(*x as u16 + 1);
+        }
+    }
+
+    fn store_x_to_y_to_mem(&mut self, x: &usize, y: &usize) -> Result<(), CpuError> {
+        let registers = register_range(*x, *y);
+        let range = self.mem_range(self.i as usize, registers.len())?;
+
+        for (location, register) in range.zip(registers) {
+            self.memory[location] = self.registers[register];
+        }
+
+        Ok(())
+    }
+
+    fn fill_x_to_y_from_mem(&mut self, x: &usize, y: &usize) -> Result<(), CpuError> {
+        let registers = register_range(*x, *y);
+        let range = self.mem_range(self.i as usize, registers.len())?;
+
+        for (location, register) in range.zip(registers) {
+            self.registers[register] = self.memory[location];
+        }
+
+        Ok(())
+    }
+
+    fn save_flags(&mut self, x: &usize) {
+        self.flags[..=*x].copy_from_slice(&self.registers[..=*x]);
+    }
+
+    fn load_flags(&mut self, x: &usize) {
+        self.registers[..=*x].copy_from_slice(&self.flags[..=*x]);
+    }
+}
+
+// VX to VY in the order 5XY2 and 5XY3 visit them, backwards when X is greater than Y
+fn register_range(x: usize, y: usize) -> Vec<usize> {
+    if x <= y {
+        (x..=y).collect()
+    } else {
+        (y..=x).rev().collect()
     }
 }
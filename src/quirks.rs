@@ -0,0 +1,76 @@
+// The instructions that CHIP-8's descendants disagree on. Each flag picks one of the two
+// behaviours, and the presets match the interpreters ROMs were written for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    // 8XY6 and 8XYE shift VY into VX rather than shifting VX in place
+    pub shift_uses_vy: bool,
+    // 8XY1, 8XY2 and 8XY3 set VF to 0
+    pub logic_resets_vf: bool,
+    // FX55 and FX65 leave I pointing just past the last register stored or loaded
+    pub load_store_increments_i: bool,
+    // BNNN is read as BXNN and jumps to XNN plus VX rather than NNN plus V0
+    pub jump_uses_vx: bool,
+    // Drawing waits for the next frame, so at most one sprite is drawn per frame
+    pub display_wait: bool,
+    // Sprites are cut off at the edges of the screen rather than wrapping around
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    // The original interpreter on the COSMAC VIP
+    pub fn vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            logic_resets_vf: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            display_wait: true,
+            clip_sprites: true,
+        }
+    }
+
+    // CHIP-48 on the HP48
+    pub fn chip48() -> Self {
+        Self {
+            shift_uses_vy: false,
+            logic_resets_vf: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            display_wait: false,
+            clip_sprites: true,
+        }
+    }
+
+    // SUPER-CHIP 1.1, which kept CHIP-48's behaviour
+    pub fn superchip() -> Self {
+        Self::chip48()
+    }
+
+    // XO-CHIP as implemented by Octo
+    pub fn xo_chip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            logic_resets_vf: false,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            display_wait: false,
+            clip_sprites: false,
+        }
+    }
+}
+
+// What the interpreter did before quirks could be chosen: shifts work on VX in place, the
+// logic and register instructions leave VF and I alone, BNNN adds V0, drawing never waits
+// and sprites are clipped
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            logic_resets_vf: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            display_wait: false,
+            clip_sprites: true,
+        }
+    }
+}
@@ -1,7 +1,151 @@
-const BASE: u32 = 0b0_01111110_00000000000000000000000;
+use std::fmt::Debug;
 
-fn fake(seed: u8) -> f32 {
-    let large_number = (seed as u32) << 15;
-    let m = f32::from_bits(BASE | large_number);
-    2.0 * (m - 0.5)
+pub const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+// A source of random bytes for CXNN. Every generator is fully determined by its seed so
+// runs can be reproduced. The interpreter's memory is passed in for generators that draw
+// on it.
+//
+// None of them reproduces the COSMAC VIP's numbers. Its RND routine reads the VIP
+// interpreter's own code, which this crate does not carry, and there is no known output
+// sequence here to check such a generator against.
+pub trait RandomSource: Debug {
+    fn seed(&mut self, seed: u64);
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+}
+
+// Marsaglia's xorshift64
+#[derive(Debug, Clone, PartialEq)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.seed(seed);
+        rng
+    }
+}
+
+impl RandomSource for XorShift {
+    // An all zero state would only ever produce zeros
+    fn seed(&mut self, seed: u64) {
+        self.state = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 32) as u8
+    }
+}
+
+// 16 bit Galois linear feedback shift register (taps 16, 14, 13, 11), clocked 8 times per byte
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lfsr {
+    state: u16,
+}
+
+const LFSR_TAPS: u16 = 0xB400;
+
+impl Lfsr {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.seed(seed);
+        rng
+    }
+
+    fn clock(&mut self) -> u8 {
+        let bit = (self.state & 1) as u8;
+        self.state >>= 1;
+
+        if bit == 1 {
+            self.state ^= LFSR_TAPS;
+        }
+
+        bit
+    }
+}
+
+impl RandomSource for Lfsr {
+    fn seed(&mut self, seed: u64) {
+        let folded = (seed ^ seed >> 16 ^ seed >> 32 ^ seed >> 48) as u16;
+        self.state = if folded == 0 { 0xACE1 } else { folded };
+    }
+
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        (0..8).fold(0, |byte, _| byte << 1 | self.clock())
+    }
+}
+
+// Mixes memory into the result: a pointer steps through the page at 0x100-0x1FF and each
+// byte found there is added to the previous result and to a counter that advances by a
+// fixed step per call
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryMix {
+    pointer: u8,
+    previous: u8,
+    counter: u8,
+}
+
+const MIX_TABLE_PAGE: usize = 0x100;
+const MIX_COUNTER_STEP: u8 = 0x1B;
+
+impl MemoryMix {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { pointer: 0, previous: 0, counter: 0 };
+        rng.seed(seed);
+        rng
+    }
+}
+
+impl RandomSource for MemoryMix {
+    fn seed(&mut self, seed: u64) {
+        self.pointer = seed as u8;
+        self.previous = (seed >> 8) as u8;
+        self.counter = (seed >> 16) as u8;
+    }
+
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.pointer = self.pointer.wrapping_add(1);
+        self.counter = self.counter.wrapping_add(MIX_COUNTER_STEP);
+
+        let table = memory.get(MIX_TABLE_PAGE + self.pointer as usize).copied().unwrap_or(0);
+
+        self.previous = table.wrapping_add(self.previous).wrapping_add(self.counter);
+        self.previous
+    }
+}
+
+// The generators available, for picking one by name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Generator {
+    XorShift,
+    Lfsr,
+    MemoryMix,
+}
+
+impl Generator {
+    pub fn build(self, seed: u64) -> Box<dyn RandomSource> {
+        match self {
+            Generator::XorShift => Box::new(XorShift::new(seed)),
+            Generator::Lfsr => Box::new(Lfsr::new(seed)),
+            Generator::MemoryMix => Box::new(MemoryMix::new(seed)),
+        }
+    }
+}
+
+impl std::str::FromStr for Generator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "xorshift" => Ok(Generator::XorShift),
+            "lfsr" => Ok(Generator::Lfsr),
+            "mix" => Ok(Generator::MemoryMix),
+            _ => Err(format!("unknown random generator '{}'", name)),
+        }
+    }
 }
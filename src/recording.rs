@@ -0,0 +1,221 @@
+// Records the display once per 60 Hz frame and writes the result as an animated GIF,
+// with its own LZW encoder.
+//
+//     cpu.start_recording();
+//     for _ in 0..120 { cpu.run_frame()?; }
+//     cpu.stop_recording().unwrap().save("replay.gif", &Screenshot::default())?;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use super::display::Display;
+use super::screenshot::Screenshot;
+use super::timers::TIMER_FREQUENCY;
+
+// GIF delays are in hundredths of a second, and viewers slow down anything under 2
+const CENTISECONDS: u64 = 100;
+const MIN_DELAY: u64 = 2;
+
+// Two colours need one bit, but GIF's smallest LZW code size is 2
+const MIN_CODE_SIZE: u8 = 2;
+const MAX_CODE_SIZE: u8 = 12;
+const MAX_CODE: u16 = 4095;
+const MAX_SUB_BLOCK: usize = 255;
+
+// The frames captured so far. Only changes are kept, each with the frame it appeared on.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+    changes: Vec<(u64, Display)>,
+    frames: u64,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn capture(&mut self, display: &Display) {
+        if self.changes.last().is_none_or(|(_, last)| last != display) {
+            self.changes.push((self.frames, display.clone()));
+        }
+
+        self.frames += 1;
+    }
+
+    // How many frames have been captured
+    pub fn len(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+
+    // The distinct displays with the frame each first appeared on
+    pub fn changes(&self) -> &[(u64, Display)] {
+        &self.changes
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, style: &Screenshot) -> io::Result<()> {
+        fs::write(path, self.gif(style))
+    }
+
+    // An animated GIF that loops forever, scaled and coloured like a screenshot. A change
+    // that would show for less than the shortest delay is replaced by the one after it.
+    // Displays smaller than the largest, e.g. before a switch to hi-res, are stretched to fit.
+    pub fn gif(&self, style: &Screenshot) -> Vec<u8> {
+        let columns = self.changes.iter().map(|(_, display)| display.width).max().unwrap_or(0);
+        let rows = self.changes.iter().map(|(_, display)| display.height).max().unwrap_or(0);
+        let (width, height) = (columns * style.scale, rows * style.scale);
+
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&(width as u16).to_le_bytes());
+        gif.extend_from_slice(&(height as u16).to_le_bytes());
+        // a global colour table of two entries, background first
+        gif.extend_from_slice(&[0x80, 0, 0]);
+        for colour in &[style.background, style.foreground] {
+            gif.extend_from_slice(&[colour.red, colour.green, colour.blue]);
+        }
+        // the NETSCAPE2.0 extension, looping forever
+        gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+        for (delay, display) in self.timeline() {
+            // graphic control: leave the frame in place, delay in centiseconds
+            gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x04]);
+            gif.extend_from_slice(&(delay as u16).to_le_bytes());
+            gif.extend_from_slice(&[0x00, 0x00]);
+
+            gif.push(0x2C);
+            gif.extend_from_slice(&[0, 0, 0, 0]);
+            gif.extend_from_slice(&(width as u16).to_le_bytes());
+            gif.extend_from_slice(&(height as u16).to_le_bytes());
+            gif.push(0x00);
+
+            let indices: Vec<u8> = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| display.pixel(x * display.width / width, y * display.height / height) as u8)
+                .collect();
+
+            gif.push(MIN_CODE_SIZE);
+            for block in lzw_encode(MIN_CODE_SIZE, &indices).chunks(MAX_SUB_BLOCK) {
+                gif.push(block.len() as u8);
+                gif.extend_from_slice(block);
+            }
+            gif.push(0x00);
+        }
+
+        gif.push(0x3B);
+        gif
+    }
+
+    // Each change to show with how long to show it for, in centiseconds
+    fn timeline(&self) -> Vec<(u64, &Display)> {
+        let centiseconds = |frame: u64| (frame * CENTISECONDS + TIMER_FREQUENCY as u64 / 2) / TIMER_FREQUENCY as u64;
+        let mut shown: Vec<(u64, &Display)> = Vec::new();
+
+        for (frame, display) in &self.changes {
+            let start = centiseconds(*frame);
+
+            match shown.last_mut() {
+                Some((previous, replaced)) if start - *previous < MIN_DELAY => *replaced = display,
+                _ => shown.push((start, display)),
+            }
+        }
+
+        let end = centiseconds(self.frames).max(shown.last().map_or(0, |(start, _)| start + MIN_DELAY));
+        let ends: Vec<u64> = shown.iter().skip(1).map(|(start, _)| *start).chain(Some(end)).collect();
+
+        shown.iter().zip(ends).map(|((start, display), end)| (end - start, *display)).collect()
+    }
+}
+
+// GIF flavoured LZW: variable width codes from `min_code_size + 1` bits up to 12, packed
+// least significant bit first, starting with a clear code and ending with end-of-information.
+// Codes grow and the table is reset at the same points as in giflib.
+pub fn lzw_encode(min_code_size: u8, indices: &[u8]) -> Vec<u8> {
+    let clear = 1_u16 << min_code_size;
+    let end = clear + 1;
+
+    let mut writer = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = min_code_size + 1;
+    let mut next = end + 1;
+
+    emit(&mut writer, clear, &mut code_size, next);
+
+    let mut current: Option<u16> = None;
+    for index in indices {
+        let prefix = match current {
+            None => {
+                current = Some(*index as u16);
+                continue;
+            }
+            Some(prefix) => prefix,
+        };
+
+        if let Some(code) = table.get(&(prefix, *index)) {
+            current = Some(*code);
+            continue;
+        }
+
+        emit(&mut writer, prefix, &mut code_size, next);
+        current = Some(*index as u16);
+
+        if next >= MAX_CODE {
+            emit(&mut writer, clear, &mut code_size, next);
+            table.clear();
+            code_size = min_code_size + 1;
+            next = end + 1;
+        } else {
+            table.insert((prefix, *index), next);
+            next += 1;
+        }
+    }
+
+    if let Some(prefix) = current {
+        emit(&mut writer, prefix, &mut code_size, next);
+    }
+    emit(&mut writer, end, &mut code_size, next);
+
+    writer.finish()
+}
+
+// Writes a code, widening the codes that follow once the next table entry would not fit
+fn emit(writer: &mut BitWriter, code: u16, code_size: &mut u8, next: u16) {
+    writer.write(code, *code_size);
+
+    if next >= 1 << *code_size && *code_size < MAX_CODE_SIZE {
+        *code_size += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+
+        self.bytes
+    }
+}
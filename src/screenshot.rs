@@ -0,0 +1,266 @@
+// Exports the display as an image: PBM, PGM or PNG. The PNG encoder is self-contained,
+// writing the pixel data as uncompressed (stored) deflate blocks.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use super::display::Display;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colour {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+// The CHIP-8X colour codes, one bit each for red, blue and green
+pub const CHIP8X_PALETTE: [Colour; 8] = [
+    Colour::BLACK,
+    Colour::new(0xFF, 0x00, 0x00),
+    Colour::new(0x00, 0x00, 0xFF),
+    Colour::new(0xFF, 0x00, 0xFF),
+    Colour::new(0x00, 0xFF, 0x00),
+    Colour::new(0xFF, 0xFF, 0x00),
+    Colour::new(0x00, 0xFF, 0xFF),
+    Colour::WHITE,
+];
+
+impl Colour {
+    pub const BLACK: Colour = Colour::new(0x00, 0x00, 0x00);
+    pub const WHITE: Colour = Colour::new(0xFF, 0xFF, 0xFF);
+
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    // Perceived brightness, using the Rec. 601 weights
+    pub fn grey(&self) -> u8 {
+        ((self.red as u32 * 299 + self.green as u32 * 587 + self.blue as u32 * 114) / 1000) as u8
+    }
+}
+
+// Parses "RRGGBB", optionally starting with '#'
+impl std::str::FromStr for Colour {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        let channel = |index: usize| hex.get(index..index + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok());
+
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(red), Some(green), Some(blue)) => Ok(Colour::new(red, green, blue)),
+            _ => Err(format!("'{}' is not a colour like #RRGGBB", text)),
+        }
+    }
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}", self.red, self.green, self.blue)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // Bitmap, lit pixels are written as black ink (1); the colours are not used
+    Pbm,
+    // Greymap, using the brightness of the colours
+    Pgm,
+    Png,
+}
+
+impl Format {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "pbm" => Some(Format::Pbm),
+            "pgm" => Some(Format::Pgm),
+            "png" => Some(Format::Png),
+            _ => None,
+        }
+    }
+}
+
+// How to turn the display into an image: each pixel becomes a `scale` x `scale` square
+// of `foreground` when lit and `background` when not. A display with CHIP-8X colour
+// attributes is drawn in its own colours instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Screenshot {
+    pub scale: usize,
+    pub foreground: Colour,
+    pub background: Colour,
+}
+
+impl Default for Screenshot {
+    fn default() -> Self {
+        Self { scale: 1, foreground: Colour::WHITE, background: Colour::BLACK }
+    }
+}
+
+impl Screenshot {
+    pub fn new(scale: usize, foreground: Colour, background: Colour) -> Self {
+        Self { scale, foreground, background }
+    }
+
+    pub fn encode(&self, display: &Display, format: Format) -> Vec<u8> {
+        match format {
+            Format::Pbm => self.pbm(display),
+            Format::Pgm => self.pgm(display),
+            Format::Png => self.png(display),
+        }
+    }
+
+    // Writes the image in the format named by the file's extension
+    pub fn save<P: AsRef<Path>>(&self, display: &Display, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = Format::from_path(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a .pbm, .pgm or .png file", path.display()))
+        })?;
+
+        fs::write(path, self.encode(display, format))
+    }
+
+    fn size(&self, display: &Display) -> (usize, usize) {
+        (display.width * self.scale, display.height * self.scale)
+    }
+
+    // The scaled image row by row, true where a pixel is lit
+    fn scaled_rows<'a>(&'a self, display: &'a Display) -> impl Iterator<Item = Vec<bool>> + 'a {
+        display.rows().flat_map(move |row| {
+            let scaled: Vec<bool> = row.iter().flat_map(|lit| std::iter::repeat_n(*lit, self.scale)).collect();
+            std::iter::repeat_n(scaled, self.scale)
+        })
+    }
+
+    // The scaled image row by row in colour
+    fn scaled_colours<'a>(&'a self, display: &'a Display) -> impl Iterator<Item = Vec<Colour>> + 'a {
+        display.rows().enumerate().flat_map(move |(y, row)| {
+            let scaled: Vec<Colour> = row.iter().enumerate()
+                .flat_map(|(x, lit)| std::iter::repeat_n(self.colour(display, x, y, *lit), self.scale))
+                .collect();
+            std::iter::repeat_n(scaled, self.scale)
+        })
+    }
+
+    fn colour(&self, display: &Display, x: usize, y: usize, lit: bool) -> Colour {
+        match &display.colours {
+            Some(colours) => CHIP8X_PALETTE[colours.colour(x, y, lit) as usize],
+            None if lit => self.foreground,
+            None => self.background,
+        }
+    }
+
+    pub fn pbm(&self, display: &Display) -> Vec<u8> {
+        let (width, height) = self.size(display);
+        let mut image = format!("P4\n{} {}\n", width, height).into_bytes();
+
+        for row in self.scaled_rows(display) {
+            for bits in row.chunks(8) {
+                let byte = bits.iter().enumerate().fold(0, |byte, (index, lit)| byte | (*lit as u8) << (7 - index));
+                image.push(byte);
+            }
+        }
+
+        image
+    }
+
+    pub fn pgm(&self, display: &Display) -> Vec<u8> {
+        let (width, height) = self.size(display);
+        let mut image = format!("P5\n{} {}\n255\n", width, height).into_bytes();
+        for row in self.scaled_colours(display) {
+            image.extend(row.iter().map(Colour::grey));
+        }
+
+        image
+    }
+
+    // An 8 bit RGB image
+    pub fn png(&self, display: &Display) -> Vec<u8> {
+        let (width, height) = self.size(display);
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        // bit depth, colour type (RGB), compression, filter and interlace methods
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut scanlines = Vec::with_capacity(height * (width * 3 + 1));
+        for row in self.scaled_colours(display) {
+            // each scanline starts with its filter type, none
+            scanlines.push(0);
+            for colour in row {
+                scanlines.extend_from_slice(&[colour.red, colour.green, colour.blue]);
+            }
+        }
+
+        let mut image = PNG_SIGNATURE.to_vec();
+        chunk(&mut image, b"IHDR", &header);
+        chunk(&mut image, b"IDAT", &zlib_stored(&scanlines));
+        chunk(&mut image, b"IEND", &[]);
+        image
+    }
+}
+
+fn chunk(image: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    image.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    image.extend_from_slice(kind);
+    image.extend_from_slice(data);
+
+    let crc = crc32(kind.iter().chain(data));
+    image.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream holding `data` in stored (uncompressed) deflate blocks
+pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window and no preset dictionary, the check bits make it divisible by 31
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+
+        stream.push(last as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+pub fn crc32<'a, I: IntoIterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = !0_u32;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+pub fn adler32(bytes: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1_u32, 0_u32);
+
+    for byte in bytes {
+        a = (a + *byte as u32) % MODULUS;
+        b = (b + a) % MODULUS;
+    }
+
+    b << 16 | a
+}
@@ -0,0 +1,211 @@
+// A text frontend: draws the display with half-block characters and ANSI escapes and reads
+// the keypad from a terminal in raw mode, so programs can be played over SSH.
+//
+// Keys use the usual layout, the left hand side of a QWERTY keyboard:
+//
+//     1 2 3 4        1 2 3 C
+//     Q W E R   ->   4 5 6 D
+//     A S D F        7 8 9 E
+//     Z X C V        A 0 B F
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use super::display::Display;
+use super::keypad::{Keypad, KEY_COUNT};
+
+pub const KEY_LAYOUT: [(char, u8); KEY_COUNT] = [
+    ('1', 0x1), ('2', 0x2), ('3', 0x3), ('4', 0xC),
+    ('q', 0x4), ('w', 0x5), ('e', 0x6), ('r', 0xD),
+    ('a', 0x7), ('s', 0x8), ('d', 0x9), ('f', 0xE),
+    ('z', 0xA), ('x', 0x0), ('c', 0xB), ('v', 0xF),
+];
+
+// Frames a key stays down after the terminal reports it, long enough to bridge the gap
+// before the terminal starts repeating a held key
+pub const DEFAULT_HOLD_FRAMES: u64 = 30;
+
+const ESCAPE: u8 = 0x1B;
+const CTRL_C: u8 = 0x03;
+const TAB: u8 = 0x09;
+
+pub fn key_for(c: char) -> Option<u8> {
+    let c = c.to_ascii_lowercase();
+
+    KEY_LAYOUT.iter().find(|(key, _)| *key == c).map(|(_, value)| *value)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Key(u8),
+    // Tab, pauses or resumes a recording
+    Record,
+    Quit,
+}
+
+// Turns bytes read from a raw terminal into keypad presses. Escape on its own or Ctrl-C
+// quits, other escape sequences such as the arrow keys are skipped.
+pub fn decode_input(bytes: &[u8]) -> Vec<Input> {
+    let mut inputs = Vec::new();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            CTRL_C => inputs.push(Input::Quit),
+            TAB => inputs.push(Input::Record),
+            ESCAPE if index + 1 == bytes.len() => inputs.push(Input::Quit),
+            ESCAPE => {
+                // a CSI or SS3 sequence runs up to a final byte in '@'..='~'
+                index += 2;
+                while index < bytes.len() && !(0x40..=0x7E).contains(&bytes[index]) {
+                    index += 1;
+                }
+            }
+            byte => {
+                if let Some(key) = key_for(byte as char) {
+                    inputs.push(Input::Key(key));
+                }
+            }
+        }
+        index += 1;
+    }
+
+    inputs
+}
+
+// Terminals report presses but never releases, so each press holds its key for `hold`
+// frames. Repeats from a key being held down keep extending that.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeldKeys {
+    pub hold: u64,
+    release_at: [Option<u64>; KEY_COUNT],
+}
+
+impl HeldKeys {
+    pub fn new(hold: u64) -> Self {
+        Self { hold, release_at: [None; KEY_COUNT] }
+    }
+
+    pub fn press(&mut self, keypad: &mut Keypad, key: u8, frame: u64) {
+        keypad.press(key);
+        self.release_at[(key & 0xF) as usize] = Some(frame + self.hold);
+    }
+
+    // Releases the keys whose time is up by `frame`
+    pub fn update(&mut self, keypad: &mut Keypad, frame: u64) {
+        for (key, release_at) in self.release_at.iter_mut().enumerate() {
+            if release_at.is_some_and(|due| due <= frame) {
+                keypad.release(key as u8);
+                *release_at = None;
+            }
+        }
+    }
+}
+
+impl Default for HeldKeys {
+    fn default() -> Self {
+        Self::new(DEFAULT_HOLD_FRAMES)
+    }
+}
+
+// The display as text, each character covering two pixel rows. Lines end in "\r\n" since
+// a raw terminal does not return the cursor by itself.
+pub fn render(display: &Display) -> String {
+    let mut text = String::with_capacity((display.width + 2) * display.height / 2 * 3);
+
+    for y in (0..display.height).step_by(2) {
+        for x in 0..display.width {
+            let top = display.pixel(x, y);
+            let bottom = y + 1 < display.height && display.pixel(x, y + 1);
+
+            text.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        text.push_str("\r\n");
+    }
+
+    text
+}
+
+// Redraws the display in place at the top left corner, skipping frames that did not change
+pub struct Screen<W: Write> {
+    out: W,
+    last: Option<String>,
+    // width and height of the last frame drawn
+    size: Option<(usize, usize)>,
+}
+
+impl<W: Write> Screen<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, last: None, size: None }
+    }
+
+    // Clears the terminal and hides the cursor
+    pub fn begin(&mut self) -> io::Result<()> {
+        self.last = None;
+        self.size = None;
+        self.out.write_all(b"\x1b[2J\x1b[?25l")?;
+        self.out.flush()
+    }
+
+    pub fn draw(&mut self, display: &Display) -> io::Result<()> {
+        let frame = render(display);
+        let size = (display.width, display.height);
+
+        if self.last.as_ref() != Some(&frame) {
+            // the screen is cleared when the size changes, e.g. on a switch to hi-res
+            if self.size.is_some_and(|last| last != size) {
+                self.out.write_all(b"\x1b[2J")?;
+            }
+            self.size = Some(size);
+            write!(self.out, "\x1b[H{}", frame)?;
+            self.out.flush()?;
+            self.last = Some(frame);
+        }
+
+        Ok(())
+    }
+
+    // Shows the cursor again below the display
+    pub fn end(&mut self) -> io::Result<()> {
+        self.out.write_all(b"\x1b[?25h\r\n")?;
+        self.out.flush()
+    }
+}
+
+// Puts the controlling terminal into raw, non-blocking mode with `stty` until dropped
+pub struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo", "min", "0", "time", "0"])?;
+
+        Ok(Self { saved: saved.trim().to_string() })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(io::Error::other("stty failed, is stdin a terminal?"))
+    }
+}
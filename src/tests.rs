@@ -1,15 +1,16 @@
-use crate::{processor, address::Address, opcodes::{OpCode, NIBBLE, BYTE}};
+use std::time::Duration;
+use crate::{processor::{self, Mode, RoutineFallback, Run, Step}, address::Address, opcodes::{OpCode, BYTE}, timers::{Clock, Timers}, display::{Display, EdgeMode}, keypad::{AwaitMode, KeyEvent, Keypad}, font::{self, Font}, rand::Generator, error::{CpuError, DecodeError}, instruction::Instruction, disasm::{self, Disassembler, Entry, Syntax}, expr, asm::Assembler, octo::Compiler, cli, terminal::{self, HeldKeys, Input, Screen}, screenshot::{self, Colour, Format, Screenshot, CHIP8X_PALETTE}, recording::{self, Recording}, audio::{self, Audio, Voice}, quirks::Quirks, platform::Platform, cdp1802::Cdp1802};
 
 fn make_cpu() -> processor::CPU {
+    let mut cpu = processor::CPU::new();
 
-   processor::CPU {
-        registers: [0; 16],
-        memory: [0; 0x1000],
-        program_counter: (0 as u16).into(),
-        stack: [Address (0, 0, 0); 16],
-        stack_pointer: 0,
-        i: 0,
-    }
+    // the plain CHIP-8 font only, which leaves everything from 0x0A0 free
+    cpu.font = Font::chip8(font::DEFAULT_FONT_BASE);
+    cpu.reset().unwrap();
+
+    // most tests place their program at address 0
+    cpu.program_counter = 0_u16.into();
+    cpu
 }
 
 #[test]
@@ -22,11 +23,11 @@ fn test_add_opcode() {
     cpu.registers[3] = 10;
 
     let mut loc = 0;
-    loc = cpu.add_to_mem(loc, &OpCode::add(0x0, 0x1));
-    loc = cpu.add_to_mem(loc, &OpCode::add(0x0, 0x2));
-    cpu.add_to_mem(loc, &OpCode::add(0x0, 0x3));
+    loc = cpu.add_to_mem(loc, &OpCode::add(0x0, 0x1)).unwrap();
+    loc = cpu.add_to_mem(loc, &OpCode::add(0x0, 0x2)).unwrap();
+    cpu.add_to_mem(loc, &OpCode::add(0x0, 0x3)).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     assert_eq!(cpu.registers[0], 35);
 }
@@ -41,7 +42,7 @@ fn test_raw_memory_copy() {
         0x00, 0xEE,
     ];
 
-    cpu.raw_copy_to_mem(0x100 , &data);
+    cpu.raw_copy_to_mem(0x100 , &data).unwrap();
 
     assert_eq!(cpu.memory[0x100..0x106], data);
 
@@ -57,7 +58,7 @@ fn test_memory_copy() {
         OpCode::ret(),
     ];
 
-    cpu.copy_to_mem(0x100, &data);
+    cpu.copy_to_mem(0x100, &data).unwrap();
 
     let cpu_mem: Vec<u16> = cpu.memory[0x100..0x106].chunks(2).map(|d| (*d.first().unwrap() as u16) << BYTE | *d.last().unwrap() as u16).collect();
     let data: Vec<u16> = data.iter().map(|oc| (oc.high_byte() as u16) << BYTE | oc.low_byte() as u16).collect();
@@ -75,7 +76,7 @@ fn test_memory_vs_raw() {
         OpCode::ret(),
     ];
 
-    cpu.copy_to_mem(0x100, &data);
+    cpu.copy_to_mem(0x100, &data).unwrap();
 
     let data: [u16; 3] = [
         0x8014,
@@ -95,12 +96,12 @@ fn test_shift_right() {
     cpu.registers[0] = 255;
     cpu.registers[1] = 0;
 
-    cpu.add_to_mem(0, &OpCode::shift_right(0x0, 0x1));
+    cpu.add_to_mem(0, &OpCode::shift_right(0x0, 0x1)).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     assert_eq!(cpu.registers[0], 127);
-    assert_eq!(cpu.registers[1], 1);
+    assert_eq!(cpu.registers[0xF], 1);
 }
 
 #[test]
@@ -110,12 +111,12 @@ fn test_shift_left() {
     cpu.registers[0] = 5;
     cpu.registers[1] = 0;
 
-    cpu.add_to_mem(0, &OpCode::shift_left(0x0, 0x1));
+    cpu.add_to_mem(0, &OpCode::shift_left(0x0, 0x1)).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     assert_eq!(cpu.registers[0], 10);
-    assert_eq!(cpu.registers[1], 1);
+    assert_eq!(cpu.registers[0xF], 0);
 }
 
 #[test]
@@ -132,10 +133,10 @@ fn test_goto() {
 
     let loc2: usize = 0x100;
     
-    cpu.copy_to_mem(loc1, &goto);
+    cpu.copy_to_mem(loc1, &goto).unwrap();
 
     let stack_counter = cpu.stack_pointer;
-    cpu.run();
+    cpu.run().unwrap();
     let opcode_length = 2;
     assert_eq!(cpu.program_counter, loc2 + opcode_length);
     assert_eq!(stack_counter, cpu.stack_pointer);
@@ -164,10 +165,10 @@ fn test_call_and_return() {
         OpCode::ret(),
     ];
     
-    cpu.copy_to_mem(loc1, &call_function);
-    cpu.copy_to_mem(loc2, &add_twice);
+    cpu.copy_to_mem(loc1, &call_function).unwrap();
+    cpu.copy_to_mem(loc2, &add_twice).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     assert_eq!(cpu.registers[0], 45);
 }
@@ -191,10 +192,10 @@ fn test_skip_if_x_eql_nn() {
         OpCode::add(0x0, 0x1),
     ];
 
-    cpu.copy_to_mem(loc1, &skip_test);
-    cpu.copy_to_mem(loc2, &add_twice);
+    cpu.copy_to_mem(loc1, &skip_test).unwrap();
+    cpu.copy_to_mem(loc2, &add_twice).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     let opcode_length = 2;
     assert_eq!(cpu.program_counter, loc1 + (opcode_length * 3));
@@ -219,10 +220,10 @@ fn test_skip_if_x_ne_eql_nn() {
         OpCode::add(0x0, 0x1),
     ];
 
-    cpu.copy_to_mem(loc1, &skip_test);
-    cpu.copy_to_mem(loc2, &add_twice);
+    cpu.copy_to_mem(loc1, &skip_test).unwrap();
+    cpu.copy_to_mem(loc2, &add_twice).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     let opcode_length = 2;
     assert_eq!(cpu.program_counter, loc1 + (opcode_length * 3));
@@ -248,10 +249,10 @@ fn test_skip_if_x_e_eql_y() {
         OpCode::add(0x0, 0x1),
     ];
 
-    cpu.copy_to_mem(loc1, &skip_test);
-    cpu.copy_to_mem(loc2, &add_twice);
+    cpu.copy_to_mem(loc1, &skip_test).unwrap();
+    cpu.copy_to_mem(loc2, &add_twice).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     let opcode_length = 2;
     assert_eq!(cpu.program_counter, loc1 + (opcode_length * 3));
@@ -277,10 +278,10 @@ fn test_skip_if_x_e_neql_y() {
         OpCode::add(0x0, 0x1),
     ];
 
-    cpu.copy_to_mem(loc1, &skip_test);
-    cpu.copy_to_mem(loc2, &add_twice);
+    cpu.copy_to_mem(loc1, &skip_test).unwrap();
+    cpu.copy_to_mem(loc2, &add_twice).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     let opcode_length = 2;
     assert_eq!(cpu.program_counter, loc1 + (opcode_length * 3));
@@ -292,9 +293,9 @@ fn test_set_x_to_nn() {
 
     cpu.registers[0] = 5;
 
-    cpu.add_to_mem(0, &OpCode::set_x_to_nn(0x0, 0x0, 0x1A));
+    cpu.add_to_mem(0, &OpCode::set_x_to_nn(0x0, 0x0, 0x1A)).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     assert_eq!(cpu.registers[0], 26);
 }
@@ -305,9 +306,2160 @@ fn test_add_nn_to_x() {
 
     cpu.registers[0] = 5;
 
-    cpu.add_to_mem(0, &OpCode::add_nn_to_x(0x0, 0x0, 0x6));
+    cpu.add_to_mem(0, &OpCode::add_nn_to_x(0x0, 0x0, 0x6)).unwrap();
 
-    cpu.run();
+    cpu.run().unwrap();
 
     assert_eq!(cpu.registers[0], 11);
 }
+
+#[test]
+fn test_timer_registers() {
+    let mut cpu = make_cpu();
+
+    cpu.registers[0] = 42;
+    cpu.registers[1] = 7;
+
+    let program: [OpCode; 3] = [
+        OpCode::set_timer_to_x(0x0),
+        OpCode::set_sound_timer(0x1),
+        OpCode::set_x_to_timer(0x2),
+    ];
+
+    cpu.copy_to_mem(0, &program).unwrap();
+
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.timers.delay, 42);
+    assert_eq!(cpu.timers.sound, 7);
+    assert_eq!(cpu.registers[2], 42);
+}
+
+#[test]
+fn test_await_key() {
+    let mut cpu = make_cpu();
+
+    cpu.keypad.press(0xB);
+
+    cpu.add_to_mem(0, &OpCode::await_key(0x3)).unwrap();
+
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[3], 0xB);
+}
+
+#[test]
+fn test_add_x_to_i() {
+    let mut cpu = make_cpu();
+
+    cpu.i = 0x300;
+    cpu.registers[0] = 0x10;
+    cpu.registers[0xF] = 9;
+
+    cpu.add_to_mem(0, &OpCode::add_x_to_i(0x0)).unwrap();
+
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.i, 0x310);
+    assert_eq!(cpu.registers[0xF], 9);
+}
+
+#[test]
+fn test_set_i_to_sprite_addr() {
+    let mut cpu = make_cpu();
+
+    cpu.registers[4] = 0xA;
+
+    cpu.add_to_mem(0, &OpCode::set_i_to_sprite_addr(0x4)).unwrap();
+
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.i, font::DEFAULT_FONT_BASE + 0xA * font::SMALL_GLYPH_LENGTH);
+}
+
+#[test]
+fn test_store_bcd() {
+    let mut cpu = make_cpu();
+
+    cpu.i = 0x300;
+    cpu.registers[0] = 254;
+
+    cpu.add_to_mem(0, &OpCode::store_bcd(0x0)).unwrap();
+
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.memory[0x300..0x303], [2, 5, 4]);
+    assert_eq!(cpu.i, 0x300);
+}
+
+#[test]
+fn test_store_and_fill_registers() {
+    let mut cpu = make_cpu();
+
+    cpu.i = 0x300;
+    cpu.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+    let program: [OpCode; 2] = [
+        OpCode::store_0_to_x_to_mem(0x2),
+        OpCode::fill_0_to_x_to_mem(0x1),
+    ];
+
+    cpu.copy_to_mem(0, &program).unwrap();
+    cpu.memory[0x300..0x304].copy_from_slice(&[0, 0, 0, 9]);
+
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.memory[0x300..0x304], [1, 2, 3, 9]);
+    assert_eq!(cpu.i, 0x300);
+
+    cpu.memory[0x300..0x302].copy_from_slice(&[7, 8]);
+    cpu.program_counter = 2_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[..4], [7, 8, 3, 4]);
+}
+
+#[test]
+fn test_timers_tick_once_per_frame() {
+    let mut cpu = make_cpu();
+
+    cpu.clock = Clock::new(4);
+    cpu.registers[0] = 3;
+
+    let program: [OpCode; 2] = [
+        OpCode::set_timer_to_x(0x0),
+        OpCode::set_sound_timer(0x0),
+    ];
+
+    cpu.copy_to_mem(0, &program).unwrap();
+    cpu.raw_copy_to_mem(0x4, &[0x10, 0x04]).unwrap(); // spin in place
+
+    assert!(cpu.run_frame().unwrap());
+    assert_eq!(cpu.timers, Timers { delay: 2, sound: 2 });
+
+    assert!(cpu.run_frame().unwrap());
+    assert!(cpu.run_frame().unwrap());
+    assert!(cpu.run_frame().unwrap());
+
+    assert_eq!(cpu.timers, Timers { delay: 0, sound: 0 });
+    assert!(!cpu.timers.is_sounding());
+    assert_eq!(cpu.clock.frames, 4);
+}
+
+#[test]
+fn test_run_frame_stops_on_halt() {
+    let mut cpu = make_cpu();
+
+    cpu.clock = Clock::new(10);
+    cpu.timers.delay = 5;
+
+    cpu.add_to_mem(0, &OpCode::add(0x0, 0x1)).unwrap();
+
+    assert!(!cpu.run_frame().unwrap());
+    assert_eq!(cpu.timers.delay, 5);
+}
+
+#[test]
+fn test_clock_advance_carries_remainder() {
+    let mut clock = Clock::default();
+
+    let frame = Clock::frame_duration();
+
+    assert_eq!(clock.advance(frame / 2), 0);
+    assert_eq!(clock.advance(frame / 2), 1);
+    assert_eq!(clock.advance(frame * 3 + frame / 2), 3);
+    assert_eq!(clock.advance(frame / 2), 1);
+}
+
+#[test]
+fn test_run_for_duration() {
+    let mut cpu = make_cpu();
+
+    cpu.timers.delay = 60;
+    cpu.raw_copy_to_mem(0, &[0x10, 0x00]).unwrap(); // spin in place
+
+    assert!(cpu.run_for_duration(Duration::from_millis(500)).unwrap());
+    assert_eq!(cpu.timers.delay, 30);
+}
+
+#[test]
+fn test_draw_sprite() {
+    let mut cpu = make_cpu();
+
+    cpu.i = 0x300;
+    cpu.registers[0] = 2;
+    cpu.registers[1] = 3;
+    cpu.raw_copy_to_mem(0x300, &[0b1100_0001, 0b0000_0011]).unwrap();
+
+    cpu.add_to_mem(0, &OpCode::draw(0x0, 0x1, 0x2)).unwrap();
+
+    cpu.run().unwrap();
+
+    assert!(cpu.display.pixel(2, 3));
+    assert!(cpu.display.pixel(3, 3));
+    assert!(!cpu.display.pixel(4, 3));
+    assert!(cpu.display.pixel(9, 3));
+    assert!(cpu.display.pixel(8, 4));
+    assert!(cpu.display.pixel(9, 4));
+    assert_eq!(cpu.display.pixels().iter().filter(|lit| **lit).count(), 5);
+    assert_eq!(cpu.registers[0xF], 0);
+}
+
+#[test]
+fn test_draw_collision_and_clear() {
+    let mut cpu = make_cpu();
+
+    cpu.i = 0x300;
+    cpu.raw_copy_to_mem(0x300, &[0xFF, 0x00]).unwrap();
+
+    let program: [OpCode; 2] = [
+        OpCode::draw(0x0, 0x0, 0x1),
+        OpCode::draw(0x0, 0x0, 0x1),
+    ];
+
+    cpu.copy_to_mem(0, &program).unwrap();
+
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0xF], 1);
+    assert!(cpu.display.pixels().iter().all(|lit| !lit));
+
+    cpu.display.set_pixel(10, 10, true);
+    cpu.add_to_mem(0x10, &OpCode::clear()).unwrap();
+    cpu.program_counter = 0x10_u16.into();
+    cpu.run().unwrap();
+
+    assert!(!cpu.display.pixel(10, 10));
+}
+
+#[test]
+fn test_draw_clip_and_wrap() {
+    let sprite = [0xFF, 0xFF];
+
+    let mut display = Display::default();
+    display.draw_sprite(60, 31, &sprite);
+
+    assert!(display.pixel(63, 31));
+    assert!(!display.pixel(0, 31));
+    assert!(!display.pixel(60, 0));
+    assert_eq!(display.pixels().iter().filter(|lit| **lit).count(), 4);
+
+    let mut display = Display::new(64, 32, EdgeMode::Wrap);
+    display.draw_sprite(60, 31, &sprite);
+
+    assert!(display.pixel(0, 31));
+    assert!(display.pixel(3, 0));
+    assert_eq!(display.pixels().iter().filter(|lit| **lit).count(), 16);
+
+    // the starting coordinate wraps in both modes
+    let mut display = Display::default();
+    display.draw_sprite(64 + 1, 32 + 2, &[0x80]);
+
+    assert!(display.pixel(1, 2));
+}
+
+#[test]
+fn test_skip_if_key() {
+    let mut cpu = make_cpu();
+
+    cpu.registers[0] = 0x5;
+    cpu.keypad.press(0x5);
+
+    let program: [OpCode; 4] = [
+        OpCode::skip_if_key(0x0),
+        OpCode::add_nn_to_x(0x1, 0x0, 0x1),
+        OpCode::skip_if_nkey(0x0),
+        OpCode::add_nn_to_x(0x2, 0x0, 0x1),
+    ];
+
+    cpu.copy_to_mem(0, &program).unwrap();
+
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[1], 0);
+    assert_eq!(cpu.registers[2], 1);
+}
+
+#[test]
+fn test_await_key_blocks_until_release() {
+    let mut cpu = make_cpu();
+
+    cpu.keypad = Keypad::new(AwaitMode::Release);
+    cpu.clock = Clock::new(1);
+    cpu.registers[3] = 0xFF;
+    cpu.keypad.tap(2, 0x7, 2);
+
+    cpu.add_to_mem(0, &OpCode::await_key(0x3)).unwrap();
+
+    for _ in 0..4 {
+        assert!(cpu.run_frame().unwrap());
+    }
+
+    assert_eq!(cpu.registers[3], 0xFF);
+    assert_eq!(cpu.program_counter, 0);
+    assert!(cpu.keypad.is_pressed(0x7));
+
+    assert!(cpu.run_frame().unwrap());
+    assert!(!cpu.keypad.is_pressed(0x7));
+    assert_eq!(cpu.registers[3], 0x7);
+    assert!(!cpu.run_frame().unwrap());
+}
+
+#[test]
+fn test_reset_abandons_key_wait() {
+    let mut cpu = make_cpu();
+
+    cpu.keypad = Keypad::new(AwaitMode::Release);
+    cpu.add_to_mem(0, &OpCode::await_key(0x3)).unwrap();
+    cpu.keypad.press(0x7);
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 0);
+
+    cpu.reset().unwrap();
+    cpu.add_to_mem(0, &OpCode::await_key(0x3)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.keypad.await_mode, AwaitMode::Release);
+    assert_eq!(cpu.program_counter, 0);
+    assert_eq!(cpu.registers[3], 0);
+}
+
+#[test]
+fn test_scripted_key_events() {
+    let mut keypad = Keypad::default();
+
+    keypad.schedule(1, KeyEvent::Press(0x1));
+    keypad.schedule(1, KeyEvent::Press(0x2));
+    keypad.schedule(3, KeyEvent::Release(0x1));
+
+    keypad.update(0);
+    assert!(!keypad.is_pressed(0x1));
+
+    keypad.update(1);
+    assert!(keypad.is_pressed(0x1));
+    assert!(keypad.is_pressed(0x2));
+
+    keypad.update(5);
+    assert!(!keypad.is_pressed(0x1));
+    assert!(keypad.is_pressed(0x2));
+    assert!(!keypad.has_scheduled());
+}
+
+#[test]
+fn test_overlapping_taps() {
+    let mut keypad = Keypad::default();
+
+    keypad.tap(0, 0x1, 10);
+    keypad.tap(2, 0x2, 1);
+    keypad.schedule(2, KeyEvent::Release(0x2));
+
+    keypad.update(2);
+    assert!(keypad.is_pressed(0x1));
+    assert!(!keypad.is_pressed(0x2));
+
+    keypad.update(3);
+    assert!(keypad.is_pressed(0x1));
+    assert!(!keypad.is_pressed(0x2));
+
+    keypad.update(9);
+    assert!(keypad.is_pressed(0x1));
+
+    keypad.update(10);
+    assert!(!keypad.is_pressed(0x1));
+    assert!(!keypad.has_scheduled());
+}
+
+#[test]
+fn test_reset_installs_font() {
+    let mut cpu = processor::CPU::new();
+
+    cpu.memory[0x300] = 0xAA;
+    cpu.registers[0] = 1;
+    cpu.reset().unwrap();
+
+    let base = font::DEFAULT_FONT_BASE as usize;
+    assert_eq!(cpu.memory[base..base + 80], font::SMALL_FONT);
+    assert_eq!(cpu.memory[base + 80..base + 240], font::LARGE_FONT[..]);
+    assert_eq!(cpu.memory[0x300], 0);
+    assert_eq!(cpu.registers[0], 0);
+}
+
+#[test]
+fn test_font_at_custom_base() {
+    let mut cpu = make_cpu();
+
+    cpu.font = Font::chip8(0x000);
+    cpu.reset().unwrap();
+
+    cpu.registers[0] = 0x3;
+
+    let program: [OpCode; 2] = [
+        OpCode::set_i_to_sprite_addr(0x0),
+        OpCode::draw(0x1, 0x1, 0x5),
+    ];
+
+    cpu.copy_to_mem(0x200, &program).unwrap();
+    cpu.program_counter = 0x200_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.i, 15);
+    assert_eq!(cpu.memory[0..5], font::SMALL_FONT[0..5]);
+
+    // the glyph for 3 has its middle row lit across all four columns
+    assert!((0..4).all(|x| cpu.display.pixel(x, 2)));
+    assert!(!cpu.display.pixel(0, 1));
+
+    cpu.font = Font::chip8(0xFC0);
+    assert_eq!(cpu.reset(), Err(CpuError::MemoryFault { location: 0xFC0, length: 80 }));
+    assert_eq!(cpu.set_mode(Mode::XoChip), Ok(()));
+    assert_eq!(cpu.set_mode(Mode::Chip8), Err(CpuError::MemoryFault { location: 0xFC0, length: 80 }));
+
+    let mut memory = [0; 0x100];
+    assert!(Font::superchip(0x20).install(&mut memory).is_err());
+    assert!(memory.iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn test_large_font() {
+    let mut cpu = processor::CPU::new();
+
+    cpu.registers[0] = 0x2;
+    cpu.add_to_mem(0x200, &OpCode::set_i_to_large_sprite_addr(0x0)).unwrap();
+    cpu.program_counter = 0x200_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.i, font::DEFAULT_FONT_BASE + 80 + 2 * font::LARGE_GLYPH_LENGTH);
+
+    cpu.font = Font::chip8(font::DEFAULT_FONT_BASE);
+    cpu.program_counter = 0x200_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.i, font::DEFAULT_FONT_BASE + 2 * font::SMALL_GLYPH_LENGTH);
+}
+
+fn rand_program(cpu: &mut processor::CPU) {
+    let program: [OpCode; 4] = [
+        OpCode::rand(0x0, 0xF, 0xF),
+        OpCode::rand(0x1, 0xF, 0xF),
+        OpCode::rand(0x2, 0x0, 0xF),
+        OpCode::rand(0x3, 0x0, 0x0),
+    ];
+
+    cpu.copy_to_mem(0, &program).unwrap();
+}
+
+#[test]
+fn test_rand_is_reproducible() {
+    let mut first = make_cpu();
+    let mut second = make_cpu();
+
+    first.seed_rng(1234);
+    second.seed_rng(1234);
+    rand_program(&mut first);
+    rand_program(&mut second);
+
+    first.run().unwrap();
+    second.run().unwrap();
+
+    assert_eq!(first.registers, second.registers);
+    assert!(first.registers[2] <= 0xF);
+    assert_eq!(first.registers[3], 0);
+}
+
+#[test]
+fn test_rand_generators() {
+    let mut memory = [0; 0x1000];
+    memory.iter_mut().enumerate().for_each(|(i, byte)| *byte = (i * 37 % 251) as u8);
+
+    for generator in [Generator::XorShift, Generator::Lfsr, Generator::MemoryMix].iter() {
+        let mut a = generator.build(99);
+        let mut b = generator.build(99);
+        let mut c = generator.build(100);
+
+        let a: Vec<u8> = (0..64).map(|_| a.next_byte(&memory)).collect();
+        let b: Vec<u8> = (0..64).map(|_| b.next_byte(&memory)).collect();
+        let c: Vec<u8> = (0..64).map(|_| c.next_byte(&memory)).collect();
+
+        assert_eq!(a, b, "{:?} is not deterministic", generator);
+        assert_ne!(a, c, "{:?} ignores its seed", generator);
+        assert!(a.iter().any(|byte| *byte != a[0]), "{:?} is stuck", generator);
+    }
+}
+
+#[test]
+fn test_rand_swap_generator() {
+    let mut cpu = make_cpu();
+
+    cpu.rng = Generator::Lfsr.build(7);
+    rand_program(&mut cpu);
+    cpu.run().unwrap();
+
+    let mut expected = Generator::Lfsr.build(7);
+    let memory = cpu.memory;
+    assert_eq!(cpu.registers[0], expected.next_byte(&memory));
+    assert_eq!(cpu.registers[1], expected.next_byte(&memory));
+}
+
+#[test]
+fn test_stack_overflow_is_an_error() {
+    let mut cpu = make_cpu();
+
+    // calls itself forever
+    cpu.add_to_mem(0x200, &OpCode::call(0x2, 0x0, 0x0)).unwrap();
+    cpu.program_counter = 0x200_u16.into();
+
+    assert_eq!(cpu.run(), Err(CpuError::StackOverflow { address: 0x200_u16.into() }));
+    assert_eq!(cpu.stack_pointer, 16);
+}
+
+#[test]
+fn test_stack_underflow_is_an_error() {
+    let mut cpu = make_cpu();
+
+    cpu.add_to_mem(0x0, &OpCode::ret()).unwrap();
+
+    assert_eq!(cpu.run(), Err(CpuError::StackUnderflow { address: 0_u16.into() }));
+}
+
+#[test]
+fn test_illegal_opcodes() {
+    let mut cpu = make_cpu();
+
+    cpu.raw_copy_to_mem(0x2, &[0x5A, 0xB1]).unwrap();
+    cpu.program_counter = 0x2_u16.into();
+
+    let err = cpu.run().unwrap_err();
+    assert_eq!(err, CpuError::IllegalOpcode { opcode: 0x5AB1, address: 0x2_u16.into() });
+    assert_eq!(err.to_string(), "illegal opcode 5ab1 at 002");
+
+    cpu.raw_copy_to_mem(0x2, &[0xFF, 0xC3]).unwrap();
+    cpu.program_counter = 0x2_u16.into();
+
+    assert_eq!(cpu.run(), Err(CpuError::IllegalOpcode { opcode: 0xFFC3, address: 0x2_u16.into() }));
+}
+
+#[test]
+fn test_quirk_presets() {
+    assert_eq!(make_cpu().quirks, Quirks::default());
+    assert_ne!(Quirks::default(), Quirks::vip());
+    assert!(Quirks::default().clip_sprites && !Quirks::default().shift_uses_vy);
+    assert_eq!(Quirks::superchip(), Quirks::chip48());
+    assert!(Quirks::vip().display_wait && !Quirks::chip48().display_wait);
+    assert!(!Quirks::xo_chip().clip_sprites && Quirks::xo_chip().shift_uses_vy);
+
+    let mut cpu = make_cpu();
+    cpu.quirks = Quirks::xo_chip();
+    cpu.reset().unwrap();
+
+    assert_eq!(cpu.quirks, Quirks::xo_chip());
+}
+
+#[test]
+fn test_shift_quirk() {
+    let mut cpu = make_cpu();
+
+    cpu.quirks = Quirks::vip();
+    cpu.registers[0] = 0x81;
+    cpu.registers[1] = 0x03;
+    cpu.add_to_mem(0, &OpCode::shift_right(0x0, 0x1)).unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0], 0x01);
+    assert_eq!(cpu.registers[1], 0x03);
+    assert_eq!(cpu.registers[0xF], 1);
+
+    cpu.quirks.shift_uses_vy = false;
+    cpu.registers[0] = 0x81;
+    cpu.add_to_mem(0, &OpCode::shift_left(0x0, 0x1)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0], 0x02);
+    assert_eq!(cpu.registers[0xF], 1);
+
+    // The flag is written after the result, so shifting VF leaves the shifted-out bit
+    cpu.registers[0xF] = 0x02;
+    cpu.add_to_mem(0, &OpCode::shift_right(0xF, 0x1)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0xF], 0);
+}
+
+#[test]
+fn test_sub_sets_flag_without_borrow() {
+    let mut cpu = make_cpu();
+
+    cpu.registers[0] = 5;
+    cpu.registers[1] = 3;
+    cpu.add_to_mem(0, &OpCode::sub(0x0, 0x1)).unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0], 2);
+    assert_eq!(cpu.registers[0xF], 1);
+
+    cpu.add_to_mem(0, &OpCode::sub_x(0x0, 0x1)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0], 1);
+    assert_eq!(cpu.registers[0xF], 1);
+
+    cpu.registers[0] = 3;
+    cpu.registers[1] = 5;
+    cpu.add_to_mem(0, &OpCode::sub(0x0, 0x1)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0], 254);
+    assert_eq!(cpu.registers[0xF], 0);
+}
+
+#[test]
+fn test_logic_quirk() {
+    let mut cpu = make_cpu();
+
+    cpu.quirks = Quirks::vip();
+    cpu.registers[0] = 0b1100;
+    cpu.registers[1] = 0b1010;
+    cpu.registers[0xF] = 7;
+    cpu.add_to_mem(0, &OpCode::or(0x0, 0x1)).unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0], 0b1110);
+    assert_eq!(cpu.registers[0xF], 0);
+
+    cpu.quirks.logic_resets_vf = false;
+    cpu.registers[0xF] = 7;
+    cpu.add_to_mem(0, &OpCode::xor(0x0, 0x1)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0], 0b0100);
+    assert_eq!(cpu.registers[0xF], 7);
+}
+
+#[test]
+fn test_load_store_quirk() {
+    let mut cpu = make_cpu();
+
+    cpu.quirks = Quirks::vip();
+    cpu.i = 0x300;
+    cpu.registers[..3].copy_from_slice(&[1, 2, 3]);
+    cpu.add_to_mem(0, &OpCode::store_0_to_x_to_mem(0x2)).unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.memory[0x300..0x303], [1, 2, 3]);
+    assert_eq!(cpu.i, 0x303);
+
+    cpu.add_to_mem(0, &OpCode::fill_0_to_x_to_mem(0x1)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.i = 0x301;
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[..2], [2, 3]);
+    assert_eq!(cpu.i, 0x303);
+}
+
+#[test]
+fn test_jump_quirk() {
+    let mut cpu = make_cpu();
+
+    cpu.quirks = Quirks::vip();
+    cpu.registers[0] = 0x10;
+    cpu.registers[1] = 0x20;
+    cpu.add_to_mem(0, &OpCode::jump_to_nnn_plus_v0(0x1, 0x2, 0x3)).unwrap();
+
+    assert!(matches!(cpu.step(), Ok(Step::Executed { opcode: 0xB123, .. })));
+    assert_eq!(cpu.program_counter, 0x133);
+
+    cpu.quirks.jump_uses_vx = true;
+    cpu.program_counter = 0_u16.into();
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.program_counter, 0x143);
+}
+
+#[test]
+fn test_display_wait_and_clip_quirks() {
+    let mut cpu = make_cpu();
+    cpu.quirks = Quirks::vip();
+    cpu.clock = Clock::new(10);
+    cpu.i = 0x300;
+    cpu.memory[0x300] = 0xFF;
+    cpu.registers[0] = 60;
+
+    let program: [OpCode; 3] = [
+        OpCode::draw(0x0, 0x1, 1),
+        OpCode::draw(0x0, 0x2, 1),
+        OpCode::goto(0x0, 0x0, 0x4),
+    ];
+    cpu.copy_to_mem(0, &program).unwrap();
+    cpu.registers[2] = 1;
+
+    cpu.run_frame().unwrap();
+
+    assert_eq!(cpu.program_counter, 2);
+    assert!(cpu.display.pixel(63, 0));
+    assert!(!cpu.display.pixel(0, 0));
+
+    cpu.run_frame().unwrap();
+    assert_eq!(cpu.program_counter, 4);
+
+    cpu.quirks = Quirks::xo_chip();
+    cpu.reset().unwrap();
+    cpu.i = 0x300;
+    cpu.memory[0x300] = 0xFF;
+    cpu.registers[0] = 60;
+    cpu.copy_to_mem(0, &program).unwrap();
+    cpu.registers[2] = 1;
+    cpu.program_counter = 0_u16.into();
+
+    cpu.run_frame().unwrap();
+
+    assert_eq!(cpu.program_counter, 4);
+    assert!(cpu.display.pixel(0, 0) && cpu.display.pixel(3, 1));
+
+    // The quirk decides how DXYN draws without touching the display's own edge mode
+    cpu.display.clear();
+    cpu.display.edge_mode = EdgeMode::Clip;
+    cpu.program_counter = 0_u16.into();
+    cpu.run_frame().unwrap();
+
+    assert!(cpu.display.pixel(0, 0));
+    assert_eq!(cpu.display.edge_mode, EdgeMode::Clip);
+    assert!(!cpu.display.draw_sprite(62, 5, &[0xFF]) && !cpu.display.pixel(0, 5));
+}
+
+#[test]
+fn test_memory_faults() {
+    let mut cpu = make_cpu();
+
+    assert_eq!(cpu.add_to_mem(0xFFF, &OpCode::clear()), Err(CpuError::MemoryFault { location: 0xFFF, length: 2 }));
+    assert_eq!(cpu.raw_copy_to_mem(0xFFE, &[1, 2, 3]), Err(CpuError::MemoryFault { location: 0xFFE, length: 3 }));
+    assert_eq!(cpu.add_to_mem(0xFFE, &OpCode::clear()), Ok(0x1000));
+
+    cpu.i = 0xFFE;
+    cpu.add_to_mem(0, &OpCode::store_bcd(0x0)).unwrap();
+
+    assert_eq!(cpu.run(), Err(CpuError::MemoryFault { location: 0xFFE, length: 3 }));
+}
+
+#[test]
+fn test_step_reports_outcome() {
+    let mut cpu = make_cpu();
+
+    let program: [OpCode; 2] = [
+        OpCode::await_key(0x0),
+        OpCode::halt(),
+    ];
+
+    cpu.copy_to_mem(0x200, &program).unwrap();
+    cpu.program_counter = 0x200_u16.into();
+
+    assert_eq!(cpu.step().unwrap(), Step::Waiting { address: 0x200_u16.into() });
+    assert_eq!(cpu.step().unwrap(), Step::Waiting { address: 0x200_u16.into() });
+
+    cpu.keypad.press(0x4);
+
+    assert_eq!(cpu.step().unwrap(), Step::Executed { address: 0x200_u16.into(), opcode: 0xF00A });
+    assert_eq!(cpu.step().unwrap(), Step::Halted { address: 0x202_u16.into() });
+    assert_eq!(cpu.registers[0], 0x4);
+}
+
+#[test]
+fn test_run_for() {
+    let mut cpu = make_cpu();
+
+    cpu.raw_copy_to_mem(0x200, &[0x70, 0x01, 0x12, 0x00]).unwrap(); // V0 += 1 forever
+    cpu.program_counter = 0x200_u16.into();
+
+    assert_eq!(cpu.run_for(10).unwrap(), Run { cycles: 10, halted: false });
+    assert_eq!(cpu.registers[0], 5);
+
+    cpu.add_to_mem(0x202, &OpCode::halt()).unwrap();
+
+    assert_eq!(cpu.run_for(10).unwrap(), Run { cycles: 2, halted: true });
+    assert_eq!(cpu.registers[0], 6);
+}
+
+#[test]
+fn test_run_until() {
+    let mut cpu = make_cpu();
+
+    cpu.raw_copy_to_mem(0x200, &[0x70, 0x01, 0x12, 0x00]).unwrap();
+    cpu.program_counter = 0x200_u16.into();
+
+    let run = cpu.run_until(|cpu| cpu.registers[0] == 3 && cpu.program_counter == 0x202).unwrap();
+
+    assert_eq!(run, Run { cycles: 5, halted: false });
+    assert_eq!(cpu.program_counter, 0x202);
+
+    // already satisfied, nothing runs
+    assert_eq!(cpu.run_until(|cpu| cpu.registers[0] == 3).unwrap().cycles, 0);
+}
+
+#[test]
+fn test_instruction_round_trip() {
+    let mut decoded = 0;
+
+    for word in 0..=u16::MAX {
+        if let Ok(instruction) = Instruction::decode(word) {
+            assert_eq!(instruction.encode(), word, "{:?} does not encode back to {:04x}", instruction, word);
+            decoded += 1;
+        }
+    }
+
+    // every word under 0x5000 and the ANNN-DXYN range are valid, the rest are sparse
+    assert!(decoded > 0x5000 + 0x4000);
+}
+
+#[test]
+fn test_instruction_decode() {
+    assert_eq!(Instruction::decode(0x00E0), Ok(Instruction::Clear));
+    assert_eq!(Instruction::decode(0x0123), Ok(Instruction::CallRoutine { nnn: Address (1, 2, 3) }));
+    assert_eq!(Instruction::decode(0x8AB4), Ok(Instruction::AddXY { x: 0xA, y: 0xB }));
+    assert_eq!(Instruction::decode(0xD125), Ok(Instruction::Draw { x: 0x1, y: 0x2, n: 0x5 }));
+    assert_eq!(Instruction::decode(0xF565), Ok(Instruction::LoadRegisters { x: 0x5 }));
+    assert_eq!(Instruction::decode(0x5121), Err(DecodeError { opcode: 0x5121 }));
+    assert_eq!(Instruction::decode(0x800F), Err(DecodeError { opcode: 0x800F }));
+    assert_eq!(Instruction::decode(0xE19F), Err(DecodeError { opcode: 0xE19F }));
+}
+
+#[test]
+fn test_opcode_builders_use_instructions() {
+    let pairs = [
+        (OpCode::goto(0x2, 0x4, 0x6), Instruction::Goto { nnn: Address (2, 4, 6) }),
+        (OpCode::skip_x_eq_nn(0x3, 0xA, 0xB), Instruction::SkipXEqNN { x: 0x3, nn: 0xAB }),
+        (OpCode::sub_x(0x1, 0x2), Instruction::SubYX { x: 0x1, y: 0x2 }),
+        (OpCode::rand(0x4, 0x0, 0xF), Instruction::Rand { x: 0x4, nn: 0x0F }),
+        (OpCode::fill_0_to_x_to_mem(0xE), Instruction::LoadRegisters { x: 0xE }),
+        (OpCode::scroll_down(0x3), Instruction::ScrollDown { n: 0x3 }),
+        (OpCode::high_res(), Instruction::HighRes),
+        (OpCode::save_flags(0x7), Instruction::SaveFlags { x: 0x7 }),
+        (OpCode::store_x_to_y_to_mem(0x3, 0x1), Instruction::StoreRange { x: 0x3, y: 0x1 }),
+        (OpCode::select_planes(0x2), Instruction::SelectPlanes { n: 0x2 }),
+        (OpCode::set_i_to_long(), Instruction::SetIToLong),
+    ];
+
+    for (opcode, instruction) in pairs.iter() {
+        assert_eq!(opcode.decode(), Ok(*instruction));
+        assert_eq!(u16::from(opcode), instruction.encode());
+    }
+}
+
+fn disasm_program() -> Vec<u8> {
+    vec![
+        0x6A, 0x02, // 200: v10 := 2
+        0xA2, 0x0C, // 202: i := 0x20C
+        0x3A, 0x02, // 204: skip if v10 == 2
+        0x22, 0x0A, // 206: call 0x20A
+        0x12, 0x08, // 208: jump to self
+        0xD0, 0x11, // 20A: sprite v0 v1 1
+        0x00, 0xEE, // 20C: return, doubles as sprite data
+        0xFF, 0x81, // 20E: sprite data, never executed
+    ]
+}
+
+#[test]
+fn test_disassemble_cowgod() {
+    let listing = Disassembler::new(Syntax::Cowgod).disassemble(&disasm_program());
+
+    let text: Vec<String> = listing.lines.iter().map(|line| line.text(Syntax::Cowgod)).collect();
+
+    assert_eq!(text, vec![
+        "LD VA, #02",
+        "LD I, #20C",
+        "SE VA, #02",
+        "CALL #20A",
+        "JP #208",
+        "DRW V0, V1, #1",
+        "RET",
+        "DB #FF, #81",
+    ]);
+    assert_eq!(listing.code().count(), 7);
+    assert_eq!(listing.lines[7].address, 0x20E);
+}
+
+#[test]
+fn test_disassemble_octo() {
+    let listing = Disassembler::new(Syntax::Octo).disassemble(&disasm_program());
+
+    let text: Vec<String> = listing.lines.iter().map(|line| line.text(Syntax::Octo)).collect();
+
+    assert_eq!(text[0], "vA := 0x02");
+    assert_eq!(text[2], "if vA != 0x02 then");
+    assert_eq!(text[3], ":call 0x20A");
+    assert_eq!(text[5], "sprite v0 v1 0x1");
+    assert_eq!(text[7], "0xFF 0x81");
+
+    let rendered = listing.to_string();
+    assert_eq!(rendered.lines().next(), Some("0200  6A02              vA := 0x02"));
+    assert_eq!(rendered.lines().count(), 8);
+}
+
+#[test]
+fn test_disassemble_unreached_is_data() {
+    // jumps over a sprite and an illegal word to reach the rest of the code
+    let rom = [0x12, 0x06, 0x3C, 0x42, 0xFF, 0xFF, 0x00, 0xE0, 0x00, 0x00];
+
+    let listing = Disassembler::new(Syntax::Cowgod).disassemble(&rom);
+
+    let entries: Vec<(u16, &Entry)> = listing.lines.iter().map(|line| (line.address, &line.entry)).collect();
+
+    assert_eq!(entries, vec![
+        (0x200, &Entry::Code(Instruction::Goto { nnn: Address (2, 0, 6) })),
+        (0x202, &Entry::Data),
+        (0x206, &Entry::Code(Instruction::Clear)),
+        (0x208, &Entry::Code(Instruction::Halt)),
+    ]);
+    assert_eq!(listing.lines[1].bytes, vec![0x3C, 0x42, 0xFF, 0xFF]);
+}
+
+#[test]
+fn test_disassemble_memory() {
+    let mut cpu = make_cpu();
+
+    cpu.add_to_mem(0x10, &OpCode::store_bcd(0x3)).unwrap();
+
+    let disassembler = Disassembler { syntax: Syntax::Octo, mode: Mode::Chip8, origin: 0x10, entry: 0x10 };
+    let listing = disassembler.disassemble(&cpu.memory[0x10..0x14]);
+
+    assert_eq!(listing.lines[0].text(Syntax::Octo), "bcd v3");
+    assert_eq!(listing.lines[1].text(Syntax::Octo), "0x00 0x00");
+
+    // Skips and bytes at the top of the address space neither overflow nor run past it
+    let disassembler = Disassembler { syntax: Syntax::Octo, mode: Mode::Chip8, origin: 0xFFFC, entry: 0xFFFC };
+    let listing = disassembler.disassemble(&[0x30, 0x00, 0x60, 0x01, 0x61, 0x02]);
+
+    assert_eq!(listing.lines.len(), 2);
+    assert_eq!(listing.code().map(|line| line.address).collect::<Vec<_>>(), [0xFFFC, 0xFFFE]);
+    assert_eq!(listing.lines[1].text(Syntax::Octo), "v0 := 0x01");
+
+    // The target of 0NNN is machine code and is listed as data
+    let listing = Disassembler::new(Syntax::Cowgod).disassemble(&[0x02, 0x06, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x01]);
+    assert_eq!(listing.code().map(|line| line.address).collect::<Vec<_>>(), [0x200, 0x202]);
+    assert_eq!(listing.lines.last().unwrap().entry, Entry::Data);
+}
+
+#[test]
+fn test_expressions() {
+    let lookup = |name: &str| match name {
+        "WIDTH" => Ok(64),
+        _ => Err(format!("unknown {}", name)),
+    };
+
+    assert_eq!(expr::evaluate("1 + 2 * 3", lookup), Ok(7));
+    assert_eq!(expr::evaluate("(1 + 2) * 3", lookup), Ok(9));
+    assert_eq!(expr::evaluate("#FF & %1010 | 0x100", lookup), Ok(0x10A));
+    assert_eq!(expr::evaluate("WIDTH / 8 - 1", lookup), Ok(7));
+    assert_eq!(expr::evaluate("1 << 4 >> 2", lookup), Ok(4));
+    assert_eq!(expr::evaluate("-$10 + ~0", lookup), Ok(-17));
+    assert_eq!(expr::evaluate("7 % 4", lookup), Ok(3));
+    assert_eq!(expr::evaluate("HEIGHT", lookup), Err("unknown HEIGHT".to_string()));
+    assert!(expr::evaluate("1 +", lookup).is_err());
+    assert!(expr::evaluate("(1", lookup).is_err());
+}
+
+#[test]
+fn test_assemble_program() {
+    let source = r#"
+        ; adds V1 to V0 three times through a subroutine
+        TIMES   equ 3
+        start:  LD V0, 5
+                LD V1, 10
+                LD V2, TIMES
+        loop:   CALL add        ; forward reference
+                ADD V2, -1
+                SE V2, 0
+                JP loop
+                HALT
+        add:    ADD V0, V1
+                RET
+    "#;
+
+    let rom = Assembler::default().assemble(source).unwrap();
+
+    assert_eq!(rom.origin, 0x200);
+    assert_eq!(rom.bytes[..8], [0x60, 0x05, 0x61, 0x0A, 0x62, 0x03, 0x22, 0x10]);
+    assert_eq!(rom.symbol("add"), Some(0x210));
+    assert_eq!(rom.symbol("TIMES"), Some(3));
+
+    let mut cpu = make_cpu();
+    rom.copy_to(&mut cpu).unwrap();
+    cpu.program_counter = 0x200_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[0], 35);
+}
+
+#[test]
+fn test_assemble_data_and_org() {
+    let source = r#"
+        JP main
+        sprite: db #F0, %10010000, "AB", -1
+        words:  dw sprite, #1234
+                org #210
+        main:   LD I, sprite + 1
+                DRW V0, V1, 2
+    "#;
+
+    let rom = Assembler::default().assemble(source).unwrap();
+
+    assert_eq!(rom.bytes[..13], [0x12, 0x10, 0xF0, 0x90, 0x41, 0x42, 0xFF, 0x02, 0x02, 0x12, 0x34, 0x00, 0x00]);
+    assert_eq!(rom.bytes[0x10..], [0xA2, 0x03, 0xD0, 0x12]);
+    assert_eq!(rom.to_string(), "main = 0x210\nsprite = 0x202\nwords = 0x207\n");
+}
+
+#[test]
+fn test_assemble_disassembly_round_trip() {
+    let original = disasm_program();
+
+    let listing = Disassembler::new(Syntax::Cowgod).disassemble(&original);
+    let source: String = listing.lines.iter().map(|line| line.text(Syntax::Cowgod) + "\n").collect();
+
+    assert_eq!(Assembler::default().assemble(&source).unwrap().bytes, original);
+
+    // CHIP-8X listings assemble back too
+    let original = [0xB2, 0x10, 0xB2, 0x13, 0xE1, 0xF2, 0xE1, 0xF5, 0x02, 0xA0];
+    let mut disassembler = Disassembler::new(Syntax::Cowgod);
+    disassembler.mode = Mode::Chip8X;
+    let listing = disassembler.disassemble(&original);
+    let source: String = listing.lines.iter().map(|line| line.text(Syntax::Cowgod) + "\n").collect();
+
+    assert!(source.contains("COL V2, V1, #3\n"));
+    assert_eq!(Assembler::default().assemble(&source).unwrap().bytes, original);
+}
+
+#[test]
+fn test_assemble_include() {
+    let dir = std::env::temp_dir().join(format!("cpu_emulator_asm_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.asm"), "include \"lib.asm\"\nLD V0, VALUE\nJP helper\n").unwrap();
+    std::fs::write(dir.join("lib.asm"), "VALUE = 42\nhelper: RET\n").unwrap();
+
+    let rom = Assembler::default().assemble_file(dir.join("main.asm"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    let rom = rom.unwrap();
+
+    assert_eq!(rom.bytes, vec![0x00, 0xEE, 0x60, 0x2A, 0x12, 0x00]);
+}
+
+#[test]
+fn test_assemble_errors() {
+    let assembler = Assembler::default();
+
+    let err = assembler.assemble("LD V0, 1\nJP nowhere\n").unwrap_err();
+    assert_eq!(err.to_string(), "<source>:2: undefined symbol 'nowhere'");
+
+    let err = assembler.assemble("LD V0, 256").unwrap_err();
+    assert_eq!(err.message, "256 does not fit in a byte");
+
+    let err = assembler.assemble("a: CLS\na: RET").unwrap_err();
+    assert_eq!((err.line, err.message.as_str()), (2, "'a' is already defined at line 1"));
+
+    let err = assembler.assemble("A = B\nB = A\nLD V0, A").unwrap_err();
+    assert!(err.message.contains("defined in terms of itself"));
+
+    let err = assembler.assemble("FLY V0").unwrap_err();
+    assert_eq!(err.message, "cannot assemble 'FLY V0'");
+
+    let err = assembler.assemble("DRW V0, V1, 16").unwrap_err();
+    assert_eq!(err.message, "16 does not fit in a nibble");
+
+    // A program may fill memory up to the last byte, but not past it
+    let rom = Assembler::new(0xFFFC).assemble("CLS\nRET\n").unwrap();
+    assert_eq!(rom.bytes, [0x00, 0xE0, 0x00, 0xEE]);
+
+    let err = Assembler::new(0xFFFC).assemble("CLS\nRET\nCLS\n").unwrap_err();
+    assert_eq!((err.line, err.message.as_str()), (3, "program runs past the end of the address space"));
+
+    let err = Assembler::new(0xFFFE).assemble("RET\nend:\n").unwrap_err();
+    assert_eq!(err.message, "label end is past the end of the address space");
+}
+
+#[test]
+fn test_compile_octo_program() {
+    let source = r#"
+        # adds v1 to v0 three times through a subroutine
+        :const TIMES 3
+        :alias count v2
+        :macro add-twice reg { reg += v1 reg += v1 }
+
+        : main
+            v0 := 5
+            v1 := 10
+            count := TIMES
+            loop
+                while count != 0
+                add
+                count -= 1
+            again
+            if v0 == 35 begin v3 := 1 else v3 := 2 end
+            add-twice v4
+            0x00 0x00
+
+        : add
+            v0 += v1
+            return
+    "#;
+
+    let rom = Compiler::default().compile(source).unwrap();
+
+    assert_eq!(rom.bytes[..12], [0x60, 0x05, 0x61, 0x0A, 0x62, 0x03, 0x42, 0x00, 0x12, 0x10, 0x22, 0x20]);
+    assert_eq!(rom.symbol("add"), Some(0x220));
+    assert_eq!(rom.symbol("TIMES"), Some(3));
+
+    let mut cpu = make_cpu();
+    rom.copy_to(&mut cpu).unwrap();
+    cpu.program_counter = 0x200_u16.into();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[..5], [35, 10, 0, 1, 20]);
+}
+
+#[test]
+fn test_compile_octo_statements() {
+    let source = r#"
+        : data 0xF0 0x90
+        : main
+            i := data
+            :calc OFFSET { data + 1 }
+            i := OFFSET
+            i := hex v3
+            sprite v0 v1 5
+            if v0 > v1 then v2 := 1
+            if v0 <= 7 then jump main
+            if v5 key then :call data
+            jump0 later
+        : later
+    "#;
+
+    let rom = Compiler::default().compile(source).unwrap();
+
+    assert_eq!(rom.bytes, vec![
+        0x12, 0x04, 0xF0, 0x90, 0xA2, 0x02, 0xA2, 0x03, 0xF3, 0x29, 0xD0, 0x15,
+        0x8F, 0x10, 0x8F, 0x05, 0x4F, 0x00, 0x62, 0x01,
+        0x6F, 0x07, 0x8F, 0x05, 0x4F, 0x01, 0x12, 0x04,
+        0xE5, 0xA1, 0x22, 0x02, 0xB2, 0x22,
+    ]);
+    assert_eq!(rom.symbol("OFFSET"), Some(0x203));
+}
+
+#[test]
+fn test_compile_octo_code_before_main() {
+    let compiler = Compiler::default();
+
+    let rom = compiler.compile("jump later : main v0 := 1 : later v1 := 2").unwrap();
+    assert_eq!(rom.bytes, vec![0x12, 0x04, 0x12, 0x06, 0x60, 0x01, 0x61, 0x02]);
+
+    let rom = compiler.compile("loop v0 += 1 again : main v1 := 2").unwrap();
+    assert_eq!(rom.bytes, vec![0x12, 0x06, 0x70, 0x01, 0x12, 0x02, 0x61, 0x02]);
+
+    // the loop counts v0 up to 3 and falls through to main
+    let rom = compiler.compile("loop while v0 != 3 v0 += 1 again : main v1 := 2 0x00 0x00").unwrap();
+    assert_eq!(rom.bytes[..2], [0x12, 0x0A]);
+
+    let mut cpu = make_cpu();
+    rom.copy_to(&mut cpu).unwrap();
+    cpu.program_counter = 0x202_u16.into();
+    cpu.run().unwrap();
+    assert_eq!(cpu.registers[..2], [3, 2]);
+
+    let rom = compiler.compile(":org 0x204 : main v0 := 1").unwrap();
+    assert_eq!(rom.bytes, vec![0x12, 0x04, 0x00, 0x00, 0x60, 0x01]);
+}
+
+#[test]
+fn test_compile_octo_errors() {
+    let compiler = Compiler::default();
+
+    let err = compiler.compile(": main\n  jump nowhere\n").unwrap_err();
+    assert_eq!(err.to_string(), "<source>:2: undefined name 'nowhere'");
+
+    let err = compiler.compile("v0 := 1").unwrap_err();
+    assert_eq!(err.message, "program has no main");
+
+    let err = compiler.compile(": main v0 := 256").unwrap_err();
+    assert_eq!(err.message, "256 does not fit in a byte");
+
+    let err = compiler.compile(": main loop v0 += 1").unwrap_err();
+    assert!(err.message.contains("has no matching again"));
+
+    let err = compiler.compile(": main else").unwrap_err();
+    assert_eq!(err.message, "else without if ... begin");
+
+    let err = compiler.compile(": main v0 **= v1").unwrap_err();
+    assert_eq!(err.message, "unknown operation '**='");
+}
+
+#[test]
+fn test_with_rom() {
+    let mut cpu = processor::CPU::with_rom(&[0x60, 0x2A, 0x00, 0x00]).unwrap();
+
+    assert_eq!(cpu.program_counter, 0x200);
+    assert_eq!(cpu.memory[0x200..0x202], [0x60, 0x2A]);
+    assert_eq!(cpu.memory[0x050..0x055], font::SMALL_FONT[0..5]);
+
+    cpu.run().unwrap();
+    assert_eq!(cpu.registers[0], 0x2A);
+
+    cpu.program_start = 0x600;
+    cpu.load_rom(&[0x61, 0x07]).unwrap();
+
+    assert_eq!(cpu.program_counter, 0x600);
+    assert_eq!(cpu.registers[0], 0);
+    assert_eq!(cpu.memory[0x200], 0);
+    assert_eq!(cpu.memory[0x600..0x602], [0x61, 0x07]);
+}
+
+#[test]
+fn test_rom_too_large() {
+    let rom = vec![0xAA; 0xE01];
+
+    let err = processor::CPU::with_rom(&rom).unwrap_err();
+    assert_eq!(err, CpuError::RomTooLarge { length: 0xE01, start: 0x200, available: 0xE00 });
+    assert_eq!(err.to_string(), "ROM of 3585 bytes does not fit in the 3584 bytes of memory from 200");
+
+    assert!(processor::CPU::with_rom(&rom[1..]).is_ok());
+}
+
+fn run_cli(args: &[&str]) -> Result<String, crate::error::CliError> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let mut out = Vec::new();
+
+    cli::run(&args, &mut out).map(|_| String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn test_cli_commands() {
+    let dir = std::env::temp_dir().join(format!("cpu_emulator_cli_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("prog.8o");
+    let rom = dir.join("prog.ch8");
+    std::fs::write(&source, ": main\n  v0 := random 0xFF\n  v1 := 3\n  loop again\n").unwrap();
+
+    let assembled = run_cli(&["asm", source.to_str().unwrap(), "--symbols"]);
+    let ran = run_cli(&["run", rom.to_str().unwrap(), "--cycles", "10", "--seed=7", "--dump-regs"]);
+    let listing = run_cli(&["disasm", rom.to_str().unwrap(), "--syntax", "cowgod"]);
+    let info = run_cli(&["info", rom.to_str().unwrap()]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(assembled.unwrap(), format!("wrote 6 bytes to {}\nmain = 0x200\n", rom.display()));
+
+    let mut cpu = processor::CPU::with_rom(&[0xC0, 0xFF]).unwrap();
+    cpu.seed_rng(7);
+    cpu.step().unwrap();
+    let expected = format!("stopped after 10 cycles\nV0={:02X} V1=03 ", cpu.registers[0]);
+    let ran = ran.unwrap();
+    assert!(ran.starts_with(&expected), "{}", ran);
+    assert!(ran.ends_with("I=0000 PC=0204 SP=0 DT=00 ST=00\n"), "{}", ran);
+
+    assert_eq!(listing.unwrap(), "0200  C0FF              RND V0, #FF\n0202  6103              LD V1, #03\n0204  1204              JP #204\n");
+    assert!(info.unwrap().contains("size: 6 bytes\nrange: 200-205\nfree: 3578 bytes\ninstructions: 3\n"));
+}
+
+#[test]
+fn test_cli_run_limits() {
+    use crate::error::CliError;
+
+    let dir = std::env::temp_dir().join(format!("cpu_emulator_limits_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("delay.ch8");
+    std::fs::write(&rom, [0x60, 0x10, 0xF0, 0x15, 0x12, 0x04]).unwrap();
+
+    // The timers tick once per frame's worth of instructions, 9 by default
+    let ran = run_cli(&["run", rom.to_str().unwrap(), "--cycles", "32", "--dump-regs"]);
+    let endless = run_cli(&["run", rom.to_str().unwrap()]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(ran.unwrap().contains("DT=0D"));
+    let err = endless.unwrap_err();
+    assert!(matches!(err, CliError::NoHalt { cycles: cli::DEFAULT_CYCLE_LIMIT }));
+    assert_eq!(err.to_string(), "did not halt within 10000000 cycles, use --cycles or --frames to run for a set time");
+}
+
+#[test]
+fn test_platforms() {
+    for platform in Platform::ALL.iter() {
+        assert_eq!(platform.name().parse::<Platform>(), Ok(*platform));
+    }
+    assert_eq!("chip-8".parse::<Platform>(), Ok(Platform::CosmacVip));
+    assert_eq!("pdp".parse::<Platform>(), Err("unknown platform 'pdp'".to_string()));
+
+    let mut cpu = processor::CPU::with_platform(Platform::XoChip);
+    assert_eq!(cpu.mode, Mode::XoChip);
+    assert_eq!(cpu.memory.len(), processor::XO_CHIP_MEMORY_SIZE);
+    assert_eq!(cpu.quirks, Quirks::xo_chip());
+    assert_eq!(cpu.clock.instructions_per_frame, 1000);
+    assert_eq!(cpu.font, Font::superchip(font::DEFAULT_FONT_BASE));
+
+    cpu.set_platform(Platform::Chip8X).unwrap();
+    assert_eq!(cpu.mode, Mode::Chip8X);
+    assert_eq!(cpu.memory.len(), processor::MEMORY_SIZE);
+    assert_eq!(cpu.program_start, 0x300);
+    assert_eq!(cpu.program_counter, 0x300);
+    assert_eq!(cpu.font.large, Vec::<u8>::new());
+    assert_eq!(cpu.memory[font::DEFAULT_FONT_BASE as usize], 0xF0);
+
+    cpu.set_platform(Platform::SuperChip).unwrap();
+    assert_eq!((cpu.mode, cpu.quirks, cpu.clock.instructions_per_frame), (Mode::SuperChip, Quirks::chip48(), 30));
+    assert_eq!(cpu.program_start, processor::PROGRAM_START);
+}
+
+#[test]
+fn test_chip8x_decode() {
+    assert_eq!(Instruction::decode_chip8x(0x02A0), Ok(Instruction::CycleBackground));
+    assert_eq!(Instruction::decode_chip8x(0xB120), Ok(Instruction::SetZoneColour { x: 0x1, y: 0x2 }));
+    assert_eq!(Instruction::decode_chip8x(0xB123), Ok(Instruction::SetRowColour { x: 0x1, y: 0x2, n: 0x3 }));
+    assert_eq!(Instruction::decode_chip8x(0xE5F2), Ok(Instruction::SkipIfKey2 { x: 0x5 }));
+    assert_eq!(Instruction::decode_chip8x(0xE5F5), Ok(Instruction::SkipIfNotKey2 { x: 0x5 }));
+    assert_eq!(Instruction::decode_chip8x(0x00E0), Ok(Instruction::Clear));
+    assert_eq!(Instruction::decode(0xB123), Ok(Instruction::JumpToNNNPlusV0 { nnn: Address (1, 2, 3) }));
+    assert_eq!(Instruction::decode(0xE5F2), Err(DecodeError { opcode: 0xE5F2 }));
+
+    for word in [0x02A0, 0xB120, 0xB12F, 0xE5F2, 0xE5F5] {
+        assert_eq!(Instruction::decode_chip8x(word).unwrap().encode(), word);
+    }
+}
+
+#[test]
+fn test_chip8x_disassembly() {
+    let rom = [0xB2, 0x10, 0xE1, 0xF2, 0x02, 0xA0, 0x13, 0x06];
+    let mut disassembler = Disassembler::new(Syntax::Cowgod);
+    disassembler.origin = 0x300;
+    disassembler.entry = 0x300;
+
+    let plain = disassembler.disassemble(&rom);
+    assert_eq!(plain.code().count(), 1);
+
+    disassembler.mode = Mode::Chip8X;
+    let listing = disassembler.disassemble(&rom);
+    assert_eq!(
+        listing.to_string(),
+        "0300  B210              COL V2, V1\n0302  E1F2              SKP2 V1\n0304  02A0              BGC\n0306  1306              JP #306\n"
+    );
+
+    let dir = std::env::temp_dir().join(format!("cpu_emulator_disasm8x_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("colours.ch8");
+    std::fs::write(&path, rom).unwrap();
+    let listed = run_cli(&["disasm", path.to_str().unwrap(), "--platform", "chip8x", "--syntax", "cowgod"]);
+    let info = run_cli(&["info", path.to_str().unwrap(), "--platform", "chip8x"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(listed.unwrap(), listing.to_string());
+    assert!(info.unwrap().contains("instructions: 4\n"));
+}
+
+#[test]
+fn test_chip8x_colours() {
+    let mut cpu = processor::CPU::with_mode(Mode::Chip8X);
+    assert!(make_cpu().display.colours.is_none());
+
+    let program: [OpCode; 4] = [
+        OpCode::cycle_background(),
+        OpCode::set_zone_colour(0x0, 0x2),
+        OpCode::set_row_colour(0x3, 0x5, 2),
+        OpCode::halt(),
+    ];
+    cpu.copy_to_mem(0x200, &program).unwrap();
+    // columns 1-2 of the second block of rows in green, then two rows from (20, 9) in aqua
+    cpu.registers[..6].copy_from_slice(&[0x11, 0x01, 4, 20, 9, 6]);
+    cpu.run().unwrap();
+
+    let colours = cpu.display.colours.as_ref().unwrap();
+    assert_eq!(colours.background, 0);
+    assert_eq!(colours.foreground(0, 4), 1);
+    assert_eq!(colours.foreground(8, 4), 4);
+    assert_eq!(colours.foreground(23, 7), 4);
+    assert_eq!(colours.foreground(24, 7), 1);
+    assert_eq!(colours.foreground(8, 8), 1);
+    assert_eq!(colours.foreground(16, 9), 6);
+    assert_eq!(colours.foreground(23, 10), 6);
+    assert_eq!(colours.foreground(16, 11), 1);
+
+    // the same word is BNNN outside CHIP-8X
+    let mut plain = make_cpu();
+    plain.copy_to_mem(0, &program[1..2]).unwrap();
+    plain.step().unwrap();
+    assert_eq!(plain.program_counter, 0x020);
+}
+
+#[test]
+fn test_chip8x_second_keypad() {
+    let mut cpu = processor::CPU::with_mode(Mode::Chip8X);
+    let program: [OpCode; 2] = [
+        OpCode::skip_if_key2(0x0),
+        OpCode::skip_if_nkey2(0x0),
+    ];
+    cpu.copy_to_mem(0x200, &program).unwrap();
+    cpu.registers[0] = 0x7;
+    cpu.keypad.press(0x7);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 0x202);
+
+    cpu.keypad2.press(0x7);
+    cpu.program_counter = 0x200_u16.into();
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 0x204);
+
+    cpu.set_mode(Mode::Chip8).unwrap();
+    cpu.copy_to_mem(0x200, &program).unwrap();
+    assert_eq!(cpu.step(), Err(CpuError::IllegalOpcode { opcode: 0xE0F2, address: 0x200_u16.into() }));
+}
+
+#[test]
+fn test_chip8x_screenshot() {
+    let mut cpu = processor::CPU::with_platform(Platform::Chip8X);
+    cpu.display.set_pixel(0, 0, true);
+    cpu.display.set_pixel(8, 0, true);
+    cpu.display.colours.as_mut().unwrap().set_foreground(1, 0, 6);
+
+    let png = Screenshot::default().png(&cpu.display);
+    let scanlines = &png[41 + 7..];
+    let red = CHIP8X_PALETTE[1];
+    let blue = CHIP8X_PALETTE[2];
+
+    assert_eq!(scanlines[1..4], [red.red, red.green, red.blue]);
+    assert_eq!(scanlines[4..7], [blue.red, blue.green, blue.blue]);
+    assert_eq!(scanlines[1 + 8 * 3..1 + 9 * 3], [0x00, 0xFF, 0xFF]);
+
+    let pgm = Screenshot::default().pgm(&cpu.display);
+    let pixels = &pgm[pgm.len() - 64 * 32..];
+    assert_eq!(pixels[..2], [red.grey(), blue.grey()]);
+}
+
+#[test]
+fn test_cdp1802_instructions() {
+    let mut memory = vec![0; 0x100];
+    let routine = [
+        0xF8, 0x03, // 00: LDI 3
+        0xA5,       // 02: PLO R5
+        0x25,       // 03: DEC R5
+        0x85,       // 04: GLO R5
+        0x3A, 0x03, // 05: BNZ 03
+        0xF8, 0xF0, // 07: LDI F0
+        0xFC, 0x20, // 09: ADI 20, carries
+        0x7C, 0x00, // 0B: ADCI 0
+        0xFF, 0x12, // 0D: SMI 12, borrows
+        0x76,       // 0F: SHRC
+        0xC0, 0x00, 0x20, // 10: LBR 0020
+    ];
+    memory[..routine.len()].copy_from_slice(&routine);
+    memory[0x20] = 0xD4; // SEP R4
+
+    let mut cdp1802 = Cdp1802::new();
+    assert_eq!(cdp1802.call(&mut memory, 0x00, 100), Some(18));
+    assert_eq!((cdp1802.d, cdp1802.df, cdp1802.r[5]), (0x7F, true, 0));
+    assert_eq!(cdp1802.r[3], 0x21);
+
+    // MARK saves X and P on the stack, RET restores them
+    cdp1802.r[2] = 0x80;
+    cdp1802.x = 0x7;
+    cdp1802.p = 0x3;
+    memory[0x21..0x25].copy_from_slice(&[0x79, 0x12, 0xE2, 0x70]); // MARK, INC R2, SEX 2, RET
+    cdp1802.step(&mut memory);
+    assert_eq!((memory[0x80], cdp1802.x, cdp1802.r[2]), (0x73, 0x3, 0x7F));
+    (0..3).for_each(|_| cdp1802.step(&mut memory));
+    assert_eq!((cdp1802.x, cdp1802.p, cdp1802.r[2], cdp1802.ie), (0x7, 0x3, 0x81, true));
+
+    memory[..2].copy_from_slice(&[0x30, 0x00]); // BR 00, forever
+    assert_eq!(cdp1802.call(&mut memory, 0x00, 50), None);
+}
+
+// Adds 5 to V0, lights the top left 8 pixels and steps I, then returns with SEP R4
+const VIP_ROUTINE: [u8; 21] = [
+    0xF8, 0x0E, 0xB6, 0xF8, 0xF0, 0xA6, 0x06, 0xFC, 0x05, 0x56,
+    0xF8, 0x0F, 0xB7, 0xF8, 0x00, 0xA7, 0xF8, 0xFF, 0x57, 0x1A, 0xD4,
+];
+
+#[test]
+fn test_machine_code_routines() {
+    let mut cpu = processor::CPU::with_platform(Platform::CosmacVip);
+    assert!(cpu.cdp1802.is_some());
+
+    cpu.copy_to_mem(0x200, &[OpCode::call_r(0x3, 0x0, 0x0), OpCode::set_x_to_nn(0x1, 0x0, 0x7)]).unwrap();
+    cpu.raw_copy_to_mem(0x300, &VIP_ROUTINE).unwrap();
+    cpu.registers[0] = 3;
+    cpu.i = 0x123;
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.registers[..2], [8, 7]);
+    assert_eq!(cpu.i, 0x124);
+    assert!((0..8).all(|x| cpu.display.pixel(x, 0)));
+    assert!(!cpu.display.pixel(8, 0));
+
+    let mut cpu = make_cpu();
+    assert!(cpu.cdp1802.is_none());
+    cpu.add_to_mem(0, &OpCode::call_r(0x3, 0x0, 0x0)).unwrap();
+
+    cpu.routine_fallback = RoutineFallback::Ignore;
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 2);
+
+    cpu.program_counter = 0_u16.into();
+    cpu.routine_fallback = RoutineFallback::Error;
+    let err = cpu.step().unwrap_err();
+    assert_eq!(err, CpuError::UnsupportedRoutine { routine: 0x300_u16.into(), address: 0_u16.into() });
+    assert_eq!(err.to_string(), "machine code routine 300 called at 000 needs an 1802");
+
+    cpu.program_counter = 0_u16.into();
+    cpu.routine_fallback = RoutineFallback::Call;
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 0x300);
+    assert_eq!(cpu.stack_pointer, 1);
+
+    assert_eq!("error".parse(), Ok(RoutineFallback::Error));
+    assert!("1802".parse::<RoutineFallback>().is_err());
+
+    // The interpreter's work area at 0xEA0-0xFFF is kept free while an 1802 is attached
+    let rom = vec![0; 0xCA1];
+    let mut cpu = processor::CPU::with_platform(Platform::CosmacVip);
+    let err = cpu.load_rom(&rom).unwrap_err();
+    assert_eq!(err, CpuError::RomTooLarge { length: 0xCA1, start: 0x200, available: 0xCA0 });
+    assert!(cpu.load_rom(&rom[1..]).is_ok());
+
+    cpu.program_start = 0xF00;
+    assert!(cpu.load_rom(&[0]).is_err());
+
+    cpu.cdp1802 = None;
+    assert!(cpu.load_rom(&[0]).is_ok());
+    cpu.program_start = 0x200;
+    assert!(cpu.load_rom(&rom).is_ok());
+}
+
+#[test]
+fn test_cli_platform() {
+    use crate::error::CliError;
+
+    let dir = std::env::temp_dir().join(format!("cpu_emulator_platform_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("prog.ch8");
+    // i := long 0x1234, v0 := 1, then halt
+    std::fs::write(&rom, [0xF0, 0x00, 0x12, 0x34, 0x60, 0x01]).unwrap();
+    let rom = rom.to_str().unwrap();
+
+    let xo = run_cli(&["run", rom, "--platform", "xo-chip", "--start", "0x8000", "--dump-regs"]);
+    let chip8 = run_cli(&["run", rom, "--start", "0x8000"]);
+    let info = run_cli(&["info", rom, "--platform", "chip8x"]);
+    let unknown = run_cli(&["run", rom, "--platform", "pdp"]);
+    let routine = dir.join("routine.ch8");
+    std::fs::write(&routine, [0x03, 0x00]).unwrap();
+    let routines = run_cli(&["run", routine.to_str().unwrap(), "--platform", "vip", "--routines", "error"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let xo = xo.unwrap();
+    assert!(xo.starts_with("halted after 3 cycles\nV0=01 "), "{}", xo);
+    assert!(xo.contains("I=1234 PC=8008"), "{}", xo);
+    assert!(matches!(chip8, Err(CliError::Usage(message)) if message == "--start of 0x8000 is outside memory"));
+    assert!(info.unwrap().contains("range: 300-305\nfree: 2970 bytes\ninstructions: 2\ndata: 0 bytes\n"));
+    assert!(matches!(unknown, Err(CliError::Usage(message)) if message == "unknown platform 'pdp'"));
+    assert!(matches!(routines, Err(CliError::Cpu(CpuError::UnsupportedRoutine { .. }))));
+}
+
+#[test]
+fn test_cli_errors() {
+    use crate::error::CliError;
+
+    assert!(matches!(run_cli(&[]), Err(CliError::Usage(_))));
+    assert!(matches!(run_cli(&["fly"]), Err(CliError::Usage(message)) if message == "unknown command 'fly'"));
+    assert!(matches!(run_cli(&["run", "a.ch8", "--fast"]), Err(CliError::Usage(message)) if message == "unknown option '--fast'"));
+    assert!(matches!(run_cli(&["run", "a.ch8", "--cycles"]), Err(CliError::Usage(message)) if message == "--cycles needs a value"));
+    assert!(matches!(run_cli(&["disasm", "/nonexistent/a.ch8"]), Err(CliError::Io { .. })));
+}
+
+#[test]
+fn test_terminal_render() {
+    let mut display = Display::new(4, 3, EdgeMode::Clip);
+    display.set_pixel(0, 0, true);
+    display.set_pixel(1, 1, true);
+    display.set_pixel(2, 0, true);
+    display.set_pixel(2, 1, true);
+    display.set_pixel(3, 2, true);
+
+    assert_eq!(terminal::render(&display), "▀▄█ \r\n   ▀\r\n");
+
+    let mut out = Vec::new();
+    let mut screen = Screen::new(&mut out);
+    screen.draw(&display).unwrap();
+    screen.draw(&display).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\x1b[H▀▄█ \r\n   ▀\r\n");
+
+    let hires = Display::new(128, 64, EdgeMode::Clip);
+    assert_eq!(terminal::render(&hires).lines().count(), 32);
+}
+
+#[test]
+fn test_terminal_clears_only_on_resize() {
+    let mut display = Display::new(4, 2, EdgeMode::Clip);
+    let mut out = Vec::new();
+    let mut screen = Screen::new(&mut out);
+
+    screen.draw(&display).unwrap();
+    display.set_pixel(0, 0, true);
+    screen.draw(&display).unwrap();
+    display.set_pixel(0, 0, false);
+    screen.draw(&display).unwrap();
+    assert!(!String::from_utf8(out.clone()).unwrap().contains("\x1b[2J"));
+
+    out.clear();
+    let mut screen = Screen::new(&mut out);
+    screen.draw(&display).unwrap();
+    display.resize(8, 4);
+    screen.draw(&display).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap().matches("\x1b[2J").count(), 1);
+}
+
+#[test]
+fn test_terminal_input() {
+    assert_eq!(terminal::key_for('1'), Some(0x1));
+    assert_eq!(terminal::key_for('R'), Some(0xD));
+    assert_eq!(terminal::key_for('x'), Some(0x0));
+    assert_eq!(terminal::key_for('v'), Some(0xF));
+    assert_eq!(terminal::key_for('p'), None);
+
+    assert_eq!(terminal::decode_input(b"qZ\x1b[A4"), vec![Input::Key(0x4), Input::Key(0xA), Input::Key(0xC)]);
+    assert_eq!(terminal::decode_input(b"w\x1b"), vec![Input::Key(0x5), Input::Quit]);
+    assert_eq!(terminal::decode_input(&[0x03]), vec![Input::Quit]);
+
+    let mut keypad = Keypad::default();
+    let mut held = HeldKeys::new(3);
+
+    held.press(&mut keypad, 0x5, 10);
+    held.update(&mut keypad, 12);
+    assert!(keypad.is_pressed(0x5));
+
+    // a repeat from the terminal extends the hold
+    held.press(&mut keypad, 0x5, 12);
+    held.update(&mut keypad, 14);
+    assert!(keypad.is_pressed(0x5));
+
+    held.update(&mut keypad, 15);
+    assert!(!keypad.is_pressed(0x5));
+}
+
+fn screenshot_display() -> Display {
+    let mut display = Display::new(3, 2, EdgeMode::Clip);
+    display.set_pixel(0, 0, true);
+    display.set_pixel(2, 1, true);
+    display
+}
+
+#[test]
+fn test_screenshot_netpbm() {
+    let display = screenshot_display();
+    let screenshot = Screenshot::new(2, Colour::new(0xFF, 0x00, 0x00), Colour::WHITE);
+
+    assert_eq!(screenshot.encode(&display, Format::Pbm), b"P4\n6 4\n\xC0\xC0\x0C\x0C".to_vec());
+
+    let pgm = screenshot.encode(&display, Format::Pgm);
+    assert!(pgm.starts_with(b"P5\n6 4\n255\n"));
+    assert_eq!(pgm[pgm.len() - 6..], [0xFF, 0xFF, 0xFF, 0xFF, 0x4C, 0x4C]);
+}
+
+#[test]
+fn test_screenshot_png() {
+    assert_eq!(screenshot::crc32(b"IEND"), 0xAE42_6082);
+    assert_eq!(screenshot::adler32(b"Wikipedia"), 0x11E6_0398);
+
+    let display = screenshot_display();
+    let png = Screenshot::new(1, Colour::new(0x11, 0x22, 0x33), Colour::BLACK).png(&display);
+
+    assert_eq!(png[..8], [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']);
+    assert_eq!(png[12..16], *b"IHDR");
+    assert_eq!(png[16..29], [0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+    assert_eq!(png[29..33], screenshot::crc32(&png[12..29]).to_be_bytes());
+    assert_eq!(png[png.len() - 12..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+
+    // a single final stored block holding the two filtered scanlines
+    let idat = &png[41..png.len() - 16];
+    let scanlines = [
+        0, 0x11, 0x22, 0x33, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0x11, 0x22, 0x33,
+    ];
+    assert_eq!(png[33..37], (idat.len() as u32).to_be_bytes());
+    assert_eq!(idat[..7], [0x78, 0x01, 0x01, 20, 0, !20, 0xFF]);
+    assert_eq!(idat[7..27], scanlines);
+    assert_eq!(idat[27..], screenshot::adler32(&scanlines).to_be_bytes());
+
+    let large = screenshot::zlib_stored(&[7; 0x10000]);
+    assert_eq!(large.len(), 2 + 5 + 0xFFFF + 5 + 1 + 4);
+    assert_eq!(large[2], 0);
+    assert_eq!(large[2 + 5 + 0xFFFF..2 + 5 + 0xFFFF + 5], [1, 1, 0, 0xFE, 0xFF]);
+}
+
+#[test]
+fn test_display_diff_and_colours() {
+    let before = screenshot_display();
+    let mut after = before.clone();
+    after.set_pixel(1, 0, true);
+    after.set_pixel(2, 1, false);
+
+    assert_eq!(before.diff(&before), vec![]);
+    assert_eq!(before.diff(&after), vec![(1, 0), (2, 1)]);
+    assert_eq!(before.diff(&Display::new(4, 2, EdgeMode::Clip)), vec![(0, 0), (2, 1)]);
+
+    assert_eq!("#1A2B3C".parse::<Colour>(), Ok(Colour::new(0x1A, 0x2B, 0x3C)));
+    assert_eq!("ffffff".parse::<Colour>(), Ok(Colour::WHITE));
+    assert!("#12345".parse::<Colour>().is_err());
+    assert_eq!(Colour::new(1, 2, 255).to_string(), "#0102FF");
+    assert_eq!(Format::from_path("shot.PNG"), Some(Format::Png));
+}
+
+// The delay and LZW data of each image in a GIF
+fn gif_frames(gif: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    let mut frames = Vec::new();
+    let mut index = 13 + 6 + 19;
+
+    while gif[index] != 0x3B {
+        assert_eq!(gif[index..index + 4], [0x21, 0xF9, 0x04, 0x04]);
+        let delay = u16::from_le_bytes([gif[index + 4], gif[index + 5]]);
+        index += 8;
+
+        assert_eq!(gif[index], 0x2C);
+        assert_eq!(gif[index + 10], 2);
+        index += 11;
+
+        let mut data = Vec::new();
+        while gif[index] != 0 {
+            let length = gif[index] as usize;
+            data.extend_from_slice(&gif[index + 1..index + 1 + length]);
+            index += 1 + length;
+        }
+        frames.push((delay, data));
+        index += 1;
+    }
+
+    frames
+}
+
+#[test]
+fn test_lzw_encode() {
+    // clear, 0, 6 (0 0), 0, then end-of-information once the codes have grown to 4 bits
+    assert_eq!(recording::lzw_encode(2, &[0, 0, 0, 0]), vec![0x84, 0x51]);
+    assert_eq!(recording::lzw_encode(2, &[]), vec![0x2C]);
+}
+
+#[test]
+fn test_recording_gif() {
+    let blank = Display::new(2, 1, EdgeMode::Clip);
+    let mut left = blank.clone();
+    left.set_pixel(0, 0, true);
+    let mut right = blank.clone();
+    right.set_pixel(1, 0, true);
+
+    let mut recording = Recording::new();
+    recording.capture(&blank);
+    recording.capture(&left);
+    for _ in 0..4 {
+        recording.capture(&right);
+    }
+
+    assert_eq!(recording.len(), 6);
+    assert_eq!(recording.changes().iter().map(|(frame, _)| *frame).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+    let gif = recording.gif(&Screenshot::new(2, Colour::new(1, 2, 3), Colour::new(4, 5, 6)));
+    assert_eq!(gif[..13], [b'G', b'I', b'F', b'8', b'9', b'a', 4, 0, 2, 0, 0x80, 0, 0]);
+    assert_eq!(gif[13..19], [4, 5, 6, 1, 2, 3]);
+    assert_eq!(gif[22..33], *b"NETSCAPE2.0");
+
+    // `left` would last a single centisecond, so `right` takes its place
+    let frames = gif_frames(&gif);
+    assert_eq!(frames.iter().map(|(delay, _)| *delay).collect::<Vec<_>>(), vec![2, 8]);
+    assert_eq!(frames[1].1, recording::lzw_encode(2, &[0, 0, 1, 1, 0, 0, 1, 1]));
+}
+
+#[test]
+fn test_cpu_recording() {
+    let mut cpu = processor::CPU::with_rom(&[0x60, 0x01, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06]).unwrap();
+    cpu.clock.instructions_per_frame = 1;
+    assert!(cpu.stop_recording().is_none());
+
+    cpu.start_recording();
+    for _ in 0..5 {
+        cpu.run_frame().unwrap();
+    }
+    let recording = cpu.stop_recording().unwrap();
+
+    assert!(cpu.recording.is_none());
+    assert_eq!(recording.len(), 6);
+    // the sprite appears on the third frame and stays
+    assert_eq!(recording.changes().iter().map(|(frame, _)| *frame).collect::<Vec<_>>(), vec![0, 2]);
+    assert_eq!(recording.changes()[1].1, cpu.display);
+}
+
+#[test]
+fn test_audio_square_wave() {
+    let mut audio = Audio::new(6000);
+    audio.tone = 1000;
+    audio.volume = 100;
+
+    audio.render_frame(true, &Voice::default());
+    audio.render_frame(false, &Voice::default());
+    let samples = audio.take_samples();
+
+    assert_eq!(samples.len(), 200);
+    assert_eq!(samples[..8], [100, 100, 100, -100, -100, -100, 100, 100]);
+    assert!(samples[100..].iter().all(|sample| *sample == 0));
+    assert!(audio.samples().is_empty());
+
+    // 1000 samples a second do not split evenly into 60 frames
+    let mut audio = Audio::new(1000);
+    for _ in 0..3 {
+        audio.render_frame(false, &Voice::default());
+    }
+    assert_eq!(audio.samples().len(), 50);
+}
+
+#[test]
+fn test_sound_timer_audio() {
+    // v0 := 2, buzzer := v0, then spin
+    let mut cpu = processor::CPU::with_rom(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+    cpu.audio = Some(Audio::new(6000));
+
+    for _ in 0..3 {
+        cpu.run_frame().unwrap();
+    }
+
+    let samples = cpu.audio.as_ref().unwrap().samples();
+    assert_eq!(samples.len(), 300);
+    assert!(samples[..200].iter().all(|sample| sample.abs() == audio::DEFAULT_VOLUME));
+    assert!(samples[200..].iter().all(|sample| *sample == 0));
+}
+
+#[test]
+fn test_wav_export() {
+    let wav = audio::wav(&[1, -2], 8000);
+
+    assert_eq!(wav.len(), 48);
+    assert_eq!(wav[..12], *b"RIFF\x28\0\0\0WAVE");
+    assert_eq!(wav[12..36], [
+        b'f', b'm', b't', b' ', 16, 0, 0, 0, 1, 0, 1, 0,
+        0x40, 0x1F, 0, 0, 0x80, 0x3E, 0, 0, 2, 0, 16, 0,
+    ]);
+    assert_eq!(wav[36..], [b'd', b'a', b't', b'a', 4, 0, 0, 0, 1, 0, 0xFE, 0xFF]);
+}
+
+#[test]
+fn test_audio_pattern() {
+    let pitch = |pitch: u8| Voice { pattern: None, pitch }.playback_rate();
+    assert_eq!(pitch(64), 4000.0);
+    assert_eq!(pitch(112), 8000.0);
+    assert_eq!(pitch(16), 2000.0);
+
+    // at 8000 bits a second and 16000 samples, every bit plays for two samples
+    let mut pattern = [0; audio::PATTERN_LENGTH];
+    pattern[0] = 0b1010_0000;
+    pattern[15] = 0x01;
+    let voice = Voice { pattern: Some(pattern), pitch: 112 };
+    let mut audio = Audio::new(16000);
+    audio.volume = 100;
+
+    audio.render_frame(true, &voice);
+    let samples = audio.take_samples();
+    assert_eq!(samples[..8], [100, 100, -100, -100, 100, 100, -100, -100]);
+    // the last bit, then back to the start
+    assert_eq!(samples[252..258], [-100, -100, 100, 100, 100, 100]);
+
+    // silence restarts the pattern
+    audio.render_frame(false, &voice);
+    audio.take_samples();
+    audio.render_frame(true, &voice);
+    assert_eq!(audio.samples()[..4], [100, 100, -100, -100]);
+}
+
+#[test]
+fn test_audio_pattern_instructions() {
+    assert_eq!(Instruction::decode(0xF002), Ok(Instruction::LoadAudioPattern));
+    assert_eq!(Instruction::decode(0xF53A), Ok(Instruction::SetPitch { x: 5 }));
+    assert_eq!(u16::from(OpCode::load_audio_pattern()), 0xF002);
+    assert_eq!(u16::from(OpCode::set_pitch(5)), 0xF53A);
+
+    assert_eq!(disasm::mnemonic(&Instruction::LoadAudioPattern, Syntax::Octo), "audio");
+    assert_eq!(disasm::mnemonic(&Instruction::SetPitch { x: 5 }, Syntax::Octo), "pitch := v5");
+    assert_eq!(disasm::mnemonic(&Instruction::LoadAudioPattern, Syntax::Cowgod), "AUDIO");
+    assert_eq!(disasm::mnemonic(&Instruction::SetPitch { x: 5 }, Syntax::Cowgod), "LD PITCH, V5");
+
+    let rom = Assembler::default().assemble("AUDIO\nLD PITCH, V5\n").unwrap();
+    assert_eq!(rom.bytes, [0xF0, 0x02, 0xF5, 0x3A]);
+    let rom = Compiler::default().compile(": main audio pitch := v5").unwrap();
+    assert_eq!(rom.bytes, [0xF0, 0x02, 0xF5, 0x3A]);
+}
+
+#[test]
+fn test_cpu_audio_pattern() {
+    let mut rom = vec![
+        0xA2, 0x10, // i := pattern
+        0xF0, 0x02, // audio
+        0x60, 0x70, // v0 := 112
+        0xF0, 0x3A, // pitch := v0
+        0x61, 0x02, // v1 := 2
+        0xF1, 0x18, // buzzer := v1
+        0x12, 0x0C, // spin
+        0x00, 0x00,
+    ];
+    rom.extend_from_slice(&[0xF0; audio::PATTERN_LENGTH]);
+    let mut cpu = processor::CPU::with_mode(Mode::XoChip);
+    cpu.load_rom(&rom).unwrap();
+    cpu.audio = Some(Audio::new(8000));
+
+    for _ in 0..3 {
+        cpu.run_frame().unwrap();
+    }
+
+    assert_eq!(cpu.voice.pitch, 112);
+    assert_eq!(cpu.voice.pattern, Some([0xF0; audio::PATTERN_LENGTH]));
+
+    // 8000 bits a second at 8000 samples is a bit per sample
+    let samples = cpu.audio.as_ref().unwrap().samples();
+    let high = audio::DEFAULT_VOLUME;
+    assert_eq!(samples[..8], [high, high, high, high, -high, -high, -high, -high]);
+    assert!(samples[266..].iter().all(|sample| *sample == 0));
+
+    cpu.reset().unwrap();
+    assert_eq!(cpu.voice, Voice::default());
+}
+
+fn make_superchip() -> processor::CPU {
+    let mut cpu = make_cpu();
+    cpu.mode = Mode::SuperChip;
+    cpu
+}
+
+#[test]
+fn test_superchip_resolution_and_large_sprites() {
+    let mut cpu = make_superchip();
+
+    cpu.i = 0x300;
+    cpu.registers[0] = 100;
+    let mut sprite = [0; 32];
+    sprite[0] = 0x80;
+    sprite[31] = 0x01;
+    cpu.raw_copy_to_mem(0x300, &sprite).unwrap();
+
+    let program: [OpCode; 3] = [
+        OpCode::high_res(),
+        OpCode::draw(0x0, 0x1, 0x0),
+        OpCode::exit(),
+    ];
+    cpu.copy_to_mem(0, &program).unwrap();
+
+    assert_eq!(cpu.run_for(10).unwrap(), Run { cycles: 3, halted: true });
+    assert!(cpu.display.is_hires());
+    assert_eq!((cpu.display.width, cpu.display.height), (128, 64));
+    assert!(cpu.display.pixel(100, 0));
+    assert!(cpu.display.pixel(115, 15));
+    assert_eq!(cpu.display.pixels().iter().filter(|lit| **lit).count(), 2);
+
+    cpu.add_to_mem(0x10, &OpCode::low_res()).unwrap();
+    cpu.program_counter = 0x10_u16.into();
+    cpu.run().unwrap();
+    assert_eq!((cpu.display.width, cpu.display.height), (64, 32));
+    assert!(cpu.display.pixels().iter().all(|lit| !lit));
+
+    cpu.display.resize(128, 64);
+    cpu.reset().unwrap();
+    assert!(!cpu.display.is_hires());
+}
+
+#[test]
+fn test_superchip_scroll() {
+    let mut cpu = make_superchip();
+
+    cpu.display.set_pixel(0, 0, true);
+    cpu.display.set_pixel(63, 31, true);
+
+    let program: [OpCode; 2] = [
+        OpCode::scroll_down(0x2),
+        OpCode::scroll_right(),
+    ];
+    cpu.copy_to_mem(0, &program).unwrap();
+    cpu.run().unwrap();
+
+    // the bottom right pixel falls off the screen
+    assert_eq!(cpu.display.diff(&Display::default()), vec![(4, 2)]);
+
+    cpu.add_to_mem(0x10, &OpCode::scroll_left()).unwrap();
+    cpu.program_counter = 0x10_u16.into();
+    cpu.run().unwrap();
+    assert_eq!(cpu.display.diff(&Display::default()), vec![(0, 2)]);
+}
+
+#[test]
+fn test_superchip_flags() {
+    let mut cpu = make_superchip();
+
+    cpu.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+    cpu.add_to_mem(0, &OpCode::save_flags(0x2)).unwrap();
+    cpu.run().unwrap();
+    assert_eq!(cpu.flags[..4], [1, 2, 3, 0]);
+
+    // the flags outlive the program
+    cpu.reset().unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.add_to_mem(0, &OpCode::load_flags(0x3)).unwrap();
+    cpu.registers[3] = 9;
+    cpu.run().unwrap();
+    assert_eq!(cpu.registers[..4], [1, 2, 3, 0]);
+}
+
+#[test]
+fn test_superchip_instructions_need_superchip_mode() {
+    let mut cpu = make_cpu();
+
+    // without SUPER-CHIP 00FF is a machine code routine, which is run as a call
+    cpu.add_to_mem(0, &OpCode::high_res()).unwrap();
+    assert_eq!(cpu.step(), Ok(Step::Executed { address: 0_u16.into(), opcode: 0x00FF }));
+    assert_eq!(cpu.program_counter, 0xFF_usize);
+    assert!(!cpu.display.is_hires());
+
+    cpu.add_to_mem(0, &OpCode::save_flags(0x1)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    assert_eq!(cpu.run(), Err(CpuError::IllegalOpcode { opcode: 0xF175, address: 0_u16.into() }));
+
+    // and DXY0 draws no rows
+    cpu.raw_copy_to_mem(0x300, &[0xFF; 32]).unwrap();
+    cpu.i = 0x300;
+    cpu.add_to_mem(0, &OpCode::draw(0x0, 0x0, 0x0)).unwrap();
+    cpu.program_counter = 0_u16.into();
+    cpu.step().unwrap();
+    assert!(cpu.display.pixels().iter().all(|lit| !lit));
+}
+
+#[test]
+fn test_superchip_assembly() {
+    let source = "SCD 3\nSCR\nSCL\nLOW\nHIGH\nLD R, V7\nLD V7, R\nEXIT\n";
+    let bytes = [0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFE, 0x00, 0xFF, 0xF7, 0x75, 0xF7, 0x85, 0x00, 0xFD];
+    assert_eq!(Assembler::default().assemble(source).unwrap().bytes, bytes);
+
+    let octo = ": main scroll-down 3 scroll-right scroll-left lores hires saveflags v7 loadflags v7 exit";
+    assert_eq!(Compiler::default().compile(octo).unwrap().bytes, bytes);
+
+    let listing = Disassembler::new(Syntax::Octo).disassemble(&bytes);
+    let text: Vec<String> = listing.code().map(|line| line.text(Syntax::Octo)).collect();
+    assert_eq!(text, [
+        "scroll-down 0x3", "scroll-right", "scroll-left", "lores", "hires", "saveflags v7", "loadflags v7", "exit",
+    ]);
+    assert_eq!(disasm::mnemonic(&Instruction::LoadFlags { x: 7 }, Syntax::Cowgod), "LD V7, R");
+}
+
+#[test]
+fn test_xo_chip_memory_and_long_i() {
+    let mut cpu = processor::CPU::with_mode(Mode::XoChip);
+    assert_eq!(cpu.memory.len(), 0x10000);
+    assert_eq!(cpu.program_counter, 0x200_usize);
+
+    let rom = [
+        0xF0, 0x00, 0xFF, 0x00, // i := long 0xFF00
+        0x30, 0x00,             // skip if v0 == 0, over all four bytes of the next one
+        0xF0, 0x00, 0x12, 0x34,
+        0x61, 0x05,             // v1 := 5
+        0x00, 0x00,
+    ];
+    cpu.load_rom(&rom).unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.i, 0xFF00);
+    assert_eq!(cpu.registers[1], 5);
+
+    // code past the first 4K runs too
+    cpu.add_to_mem(0x8000, &OpCode::set_x_to_nn(0x2, 0x4, 0x2)).unwrap();
+    cpu.program_counter = 0x8000_u16.into();
+    cpu.run().unwrap();
+    assert_eq!(cpu.registers[2], 0x42);
+    assert_eq!(u16::from(cpu.program_counter), 0x8004);
+
+    // classic machines keep 4K and have no F000
+    let mut cpu = processor::CPU::with_rom(&rom).unwrap();
+    assert_eq!(cpu.memory.len(), 0x1000);
+    assert_eq!(cpu.run(), Err(CpuError::IllegalOpcode { opcode: 0xF000, address: 0x200_u16.into() }));
+}
+
+#[test]
+fn test_xo_chip_register_ranges() {
+    let mut cpu = make_cpu();
+    cpu.set_mode(Mode::XoChip).unwrap();
+    cpu.program_counter = 0_u16.into();
+
+    cpu.i = 0x300;
+    cpu.registers[..5].copy_from_slice(&[10, 11, 12, 13, 14]);
+    let program: [OpCode; 2] = [
+        OpCode::store_x_to_y_to_mem(0x1, 0x3),
+        OpCode::fill_x_to_y_from_mem(0x4, 0x2),
+    ];
+    cpu.copy_to_mem(0, &program).unwrap();
+    cpu.run().unwrap();
+
+    assert_eq!(cpu.memory[0x300..0x304], [11, 12, 13, 0]);
+    // backwards: V4 from I, V3 from I + 1, V2 from I + 2
+    assert_eq!(cpu.registers[..5], [10, 11, 13, 12, 11]);
+    assert_eq!(cpu.i, 0x300);
+}
+
+#[test]
+fn test_xo_chip_planes() {
+    let mut cpu = make_cpu();
+    cpu.set_mode(Mode::XoChip).unwrap();
+    cpu.program_counter = 0_u16.into();
+
+    cpu.i = 0x300;
+    cpu.raw_copy_to_mem(0x300, &[0x80, 0xC0]).unwrap();
+    let program: [OpCode; 4] = [
+        OpCode::select_planes(0x2),
+        OpCode::draw(0x0, 0x0, 0x1),
+        // with both planes selected the first byte is plane 1's and the second plane 2's
+        OpCode::select_planes(0x3),
+        OpCode::draw(0x0, 0x1, 0x1),
+    ];
+    cpu.copy_to_mem(0, &program).unwrap();
+    cpu.run().unwrap();
+
+    // plane 2's pixel at (0, 0) was drawn twice and collided
+    assert_eq!(cpu.display.plane_bits(0, 0), 0b01);
+    assert_eq!(cpu.display.plane_bits(1, 0), 0b10);
+    assert_eq!(cpu.registers[0xF], 1);
+    assert!(cpu.display.pixel(0, 0));
+
+    cpu.registers[1] = 4;
+    cpu.raw_copy_to_mem(0x300, &[0xFF]).unwrap();
+    let program: [OpCode; 5] = [
+        OpCode::select_planes(0x1),
+        OpCode::draw(0x0, 0x1, 0x1),
+        OpCode::scroll_up(0x2),
+        OpCode::select_planes(0x2),
+        OpCode::clear(),
+    ];
+    cpu.copy_to_mem(0x10, &program).unwrap();
+    cpu.program_counter = 0x10_u16.into();
+    cpu.run().unwrap();
+
+    // only plane 1 scrolled, then only plane 2 was cleared
+    assert_eq!(cpu.display.plane_bits(0, 0), 0b00);
+    assert_eq!(cpu.display.plane_bits(0, 2), 0b01);
+    assert_eq!(cpu.display.pixels().iter().filter(|lit| **lit).count(), 8);
+}
+
+#[test]
+fn test_xo_chip_assembly() {
+    let source = "LD I, LONG data\nSAVE V1, V3\nLOAD V3, V1\nPLANE 3\nSCU 2\ndata: db 1\n";
+    let bytes = [0xF0, 0x00, 0x02, 0x0C, 0x51, 0x32, 0x53, 0x13, 0xF3, 0x01, 0x00, 0xD2, 0x01];
+    assert_eq!(Assembler::default().assemble(source).unwrap().bytes, bytes);
+
+    let octo = ": main i := long data save v1 - v3 load v3 - v1 plane 3 scroll-up 2 : data 1";
+    assert_eq!(Compiler::default().compile(octo).unwrap().bytes, bytes);
+
+    let listing = Disassembler::new(Syntax::Octo).disassemble(&bytes);
+    let text: Vec<String> = listing.code().map(|line| line.text(Syntax::Octo)).collect();
+    assert_eq!(text, ["i := long 0x020C", "save v1 - v3", "load v3 - v1", "plane 3", "scroll-up 0x2"]);
+
+    let listing = Disassembler::new(Syntax::Cowgod).disassemble(&bytes);
+    assert_eq!(listing.lines[0].text(Syntax::Cowgod), "LD I, LONG #020C");
+    assert_eq!(listing.lines[0].bytes.len(), 4);
+
+    // a skip steps over both words of F000 NNNN
+    let listing = Disassembler::new(Syntax::Octo).disassemble(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0x00]);
+    assert_eq!(listing.code().count(), 3);
+}
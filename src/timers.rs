@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+// Both timers count down at 60 Hz regardless of how fast instructions are executed
+pub const TIMER_FREQUENCY: u32 = 60;
+
+// A common rate for the original interpreters, roughly 540 instructions per second
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 9;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timers {
+    pub delay: u8,
+    pub sound: u8,
+}
+
+impl Timers {
+    // Decrements both timers by one, stopping at zero
+    pub fn tick(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    // The buzzer sounds for as long as the sound timer is non-zero
+    pub fn is_sounding(&self) -> bool {
+        self.sound > 0
+    }
+}
+
+// Paces execution in 60 Hz frames: a fixed number of instructions are run per frame and
+// the timers tick once at the end of each one. Hosts either drive frames directly for
+// reproducible runs or feed wall-clock time through `advance` to find how many are due.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    pub instructions_per_frame: u32,
+    pub frames: u64,
+    elapsed: Duration,
+}
+
+impl Clock {
+    pub fn new(instructions_per_frame: u32) -> Self {
+        Self {
+            instructions_per_frame,
+            frames: 0,
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    pub fn frame_duration() -> Duration {
+        Duration::from_secs(1) / TIMER_FREQUENCY
+    }
+
+    // Accumulates host time and returns how many whole frames have become due,
+    // carrying the remainder over to the next call
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.elapsed += elapsed;
+
+        let frame = Self::frame_duration();
+        let mut due = 0;
+
+        while self.elapsed >= frame {
+            self.elapsed -= frame;
+            due += 1;
+        }
+
+        due
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(DEFAULT_INSTRUCTIONS_PER_FRAME)
+    }
+}